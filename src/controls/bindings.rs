@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::storage;
+
+use super::Action;
+
+/// Stick deflection below this is noise.
+const DEAD_ZONE: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    /// Triggers report how far they are pulled.
+    Button(GamepadButtonType),
+    /// One direction of a stick.
+    Axis {
+        axis: GamepadAxisType,
+        positive: bool,
+    },
+}
+
+/// Everything bindings are read from.
+pub struct InputSources<'a> {
+    pub keys: &'a Input<KeyCode>,
+    pub mouse: &'a Input<MouseButton>,
+    pub gamepads: &'a Gamepads,
+    pub buttons: &'a Input<GamepadButton>,
+    pub button_axes: &'a Axis<GamepadButton>,
+    pub axes: &'a Axis<GamepadAxis>,
+}
+
+impl Binding {
+    /// How strongly the binding is held, from 0 to 1.
+    pub fn value(&self, sources: &InputSources) -> f32 {
+        match *self {
+            Binding::Key(key) => pressed(sources.keys.pressed(key)),
+            Binding::Mouse(button) => pressed(sources.mouse.pressed(button)),
+            Binding::Button(button_type) => sources
+                .gamepads
+                .iter()
+                .map(|gamepad| {
+                    let button = GamepadButton::new(*gamepad, button_type);
+                    let analog = sources.button_axes.get(button).unwrap_or(0.);
+                    analog.max(pressed(sources.buttons.pressed(button)))
+                })
+                .fold(0., f32::max),
+            Binding::Axis { axis, positive } => sources
+                .gamepads
+                .iter()
+                .map(|gamepad| {
+                    let value = sources
+                        .axes
+                        .get(GamepadAxis::new(*gamepad, axis))
+                        .unwrap_or(0.);
+                    let value = if positive { value } else { -value };
+                    ((value - DEAD_ZONE) / (1. - DEAD_ZONE)).clamp(0., 1.)
+                })
+                .fold(0., f32::max),
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{key:?}"),
+            Binding::Mouse(button) => format!("Mouse {button:?}"),
+            Binding::Button(button) => format!("Pad {button:?}"),
+            Binding::Axis { axis, positive } => {
+                format!("Pad {axis:?}{}", if *positive { "+" } else { "-" })
+            }
+        }
+    }
+}
+
+fn pressed(pressed: bool) -> f32 {
+    if pressed {
+        1.
+    } else {
+        0.
+    }
+}
+
+/// Inputs of every action, rebindable in the controls window and kept between sessions.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+const BINDINGS_KEY: &str = "flippingout_bindings";
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self::defaults();
+        // Actions missing from an older file keep their defaults
+        if let Some(saved) =
+            storage::load(BINDINGS_KEY).and_then(|value| ron::from_str::<Bindings>(&value).ok())
+        {
+            bindings.0.extend(saved.0);
+        }
+        bindings
+    }
+}
+
+impl Bindings {
+    pub fn defaults() -> Self {
+        use Binding::*;
+        use GamepadAxisType::*;
+        use GamepadButtonType::*;
+
+        let stick = |axis, positive| Axis { axis, positive };
+        Self(BTreeMap::from([
+            (
+                Action::Accelerate,
+                vec![
+                    Key(KeyCode::D),
+                    Button(RightTrigger2),
+                    stick(LeftStickX, true),
+                ],
+            ),
+            (
+                Action::Brake,
+                vec![
+                    Key(KeyCode::A),
+                    Button(LeftTrigger2),
+                    stick(LeftStickX, false),
+                ],
+            ),
+            (
+                Action::TiltForward,
+                vec![Key(KeyCode::S), stick(LeftStickY, false)],
+            ),
+            (
+                Action::TiltBack,
+                vec![Key(KeyCode::W), stick(LeftStickY, true)],
+            ),
+            (
+                Action::Nail,
+                vec![Mouse(MouseButton::Left), Key(KeyCode::Q), Button(South)],
+            ),
+            (
+                Action::Cancel,
+                vec![Mouse(MouseButton::Right), Key(KeyCode::E), Button(East)],
+            ),
+            (Action::CycleMode, vec![Key(KeyCode::Tab), Button(West)]),
+            (Action::Detach, vec![Key(KeyCode::F), Button(DPadDown)]),
+            (Action::Undo, vec![Key(KeyCode::Back), Button(Select)]),
+            (
+                Action::RotateLeft,
+                vec![Key(KeyCode::Z), Button(LeftTrigger)],
+            ),
+            (
+                Action::RotateRight,
+                vec![Key(KeyCode::X), Button(RightTrigger)],
+            ),
+            (Action::Flip, vec![Key(KeyCode::C), Button(DPadLeft)]),
+            (Action::Snap, vec![Key(KeyCode::V), Button(DPadRight)]),
+            (Action::Respawn, vec![Key(KeyCode::R), Button(North)]),
+            (Action::Pause, vec![Key(KeyCode::Escape), Button(Start)]),
+            (Action::Save, vec![Key(KeyCode::F5)]),
+            (Action::Load, vec![Key(KeyCode::F9)]),
+            (Action::CursorLeft, vec![stick(RightStickX, false)]),
+            (Action::CursorRight, vec![stick(RightStickX, true)]),
+            (Action::CursorUp, vec![stick(RightStickY, true)]),
+            (Action::CursorDown, vec![stick(RightStickY, false)]),
+        ]))
+    }
+
+    pub fn save(&self) {
+        let result = ron::to_string(self)
+            .map_err(|e| e.to_string())
+            .and_then(|value| storage::save(BINDINGS_KEY, &value));
+        if let Err(e) = result {
+            error!("Failed to save bindings: {e}");
+        }
+    }
+}
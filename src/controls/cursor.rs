@@ -0,0 +1,45 @@
+use bevy::prelude::*;
+
+use super::{Action, Actions};
+
+/// Screen position the nailgun aims at, follows the mouse and can be pushed around with a stick.
+#[derive(Debug)]
+pub struct VirtualCursor {
+    pub position: Option<Vec2>,
+    /// Pixels per second at full stick deflection.
+    pub speed: f32,
+}
+
+impl Default for VirtualCursor {
+    fn default() -> Self {
+        Self {
+            position: None,
+            speed: 900.,
+        }
+    }
+}
+
+pub fn move_cursor(
+    windows: Res<Windows>,
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut moved: EventReader<CursorMoved>,
+    mut cursor: ResMut<VirtualCursor>,
+) {
+    if let Some(event) = moved.iter().last() {
+        cursor.position = Some(event.position);
+    }
+    let stick = Vec2::new(
+        actions.axis(Action::CursorRight, Action::CursorLeft),
+        actions.axis(Action::CursorUp, Action::CursorDown),
+    );
+    if stick != Vec2::ZERO {
+        let Some(window) = windows.get_primary() else {
+            return;
+        };
+        let size = Vec2::new(window.width(), window.height());
+        let position = cursor.position.unwrap_or(size / 2.);
+        cursor.position =
+            Some((position + stick * cursor.speed * time.delta_seconds()).clamp(Vec2::ZERO, size));
+    }
+}
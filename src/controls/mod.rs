@@ -0,0 +1,291 @@
+use bevy::{input::InputSystem, prelude::*, utils::HashMap};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use self::{
+    bindings::{Binding, Bindings, InputSources},
+    cursor::{move_cursor, VirtualCursor},
+};
+
+pub mod bindings;
+pub mod cursor;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    Accelerate,
+    /// Brakes and reverses.
+    Brake,
+    TiltForward,
+    TiltBack,
+    /// Picks up a package or nails the held one.
+    Nail,
+    Cancel,
+    /// Switches what kind of joint the nailgun makes.
+    CycleMode,
+    /// Takes nailed cargo off again to move it.
+    Detach,
+    /// Takes back the last nail.
+    Undo,
+    /// Turns the held item, the mouse wheel does too.
+    RotateLeft,
+    RotateRight,
+    /// Mirrors the held item.
+    Flip,
+    /// Toggles snapping the held item to fixed angles.
+    Snap,
+    Respawn,
+    Pause,
+    /// Saves the world.
+    Save,
+    /// Starts the run over on the saved world.
+    Load,
+    CursorLeft,
+    CursorRight,
+    CursorUp,
+    CursorDown,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Accelerate => "Accelerate",
+            Action::Brake => "Brake / reverse",
+            Action::TiltForward => "Tilt forward",
+            Action::TiltBack => "Tilt back",
+            Action::Nail => "Pick up / nail",
+            Action::Cancel => "Cancel",
+            Action::CycleMode => "Attachment mode",
+            Action::Detach => "Detach",
+            Action::Undo => "Undo nail",
+            Action::RotateLeft => "Rotate left",
+            Action::RotateRight => "Rotate right",
+            Action::Flip => "Flip item",
+            Action::Snap => "Angle snapping",
+            Action::Respawn => "Respawn",
+            Action::Pause => "Pause",
+            Action::Save => "Save",
+            Action::Load => "Load",
+            Action::CursorLeft => "Cursor left",
+            Action::CursorRight => "Cursor right",
+            Action::CursorUp => "Cursor up",
+            Action::CursorDown => "Cursor down",
+        }
+    }
+}
+
+/// How strongly every action is held this frame, gameplay reads this instead of raw input.
+#[derive(Debug, Default)]
+pub struct Actions {
+    values: HashMap<Action, f32>,
+    previous: HashMap<Action, f32>,
+}
+
+impl Actions {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.)
+    }
+
+    pub fn pressed(&self, action: Action) -> bool {
+        self.value(action) > 0.5
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.pressed(action) && self.previous.get(&action).copied().unwrap_or(0.) <= 0.5
+    }
+
+    /// Like `just_pressed`, but systems running later this frame won't see the press anymore.
+    pub fn consume(&mut self, action: Action) -> bool {
+        let pressed = self.just_pressed(action);
+        if pressed {
+            self.previous.insert(action, 1.);
+        }
+        pressed
+    }
+
+    /// `positive` minus `negative`, from -1 to 1.
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.value(positive) - self.value(negative)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_actions(
+    mut egui_context: ResMut<EguiContext>,
+    bindings: Res<Bindings>,
+    window: Res<ControlsWindow>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut actions: ResMut<Actions>,
+) {
+    // Clicks on a window don't reach the game
+    let no_mouse = Input::default();
+    let mouse = if egui_context.ctx_mut().is_pointer_over_area() {
+        &no_mouse
+    } else {
+        &*mouse
+    };
+    let sources = InputSources {
+        keys: &keys,
+        mouse,
+        gamepads: &gamepads,
+        buttons: &buttons,
+        button_axes: &button_axes,
+        axes: &axes,
+    };
+    let actions = &mut *actions;
+    actions.previous = std::mem::take(&mut actions.values);
+    bindings.0.iter().for_each(|(action, bindings)| {
+        let value = bindings
+            .iter()
+            .map(|binding| binding.value(&sources))
+            .fold(0., f32::max);
+        actions.values.insert(*action, value);
+    });
+    // Presses that are being bound don't trigger anything
+    if window.capturing.is_some() {
+        actions.previous = actions.values.clone();
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ControlsWindow {
+    pub open: bool,
+    /// Action the next input gets bound to.
+    capturing: Option<Action>,
+}
+
+pub fn controls_window(
+    mut egui_context: ResMut<EguiContext>,
+    mut window: ResMut<ControlsWindow>,
+    mut bindings: ResMut<Bindings>,
+) {
+    if !window.open {
+        return;
+    }
+    let mut changed = false;
+    let mut open = true;
+    egui::Window::new("Controls")
+        .open(&mut open)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("bindings").show(ui, |ui| {
+                bindings.0.iter_mut().for_each(|(action, bindings)| {
+                    ui.label(action.name());
+                    ui.horizontal(|ui| {
+                        let mut removed = None;
+                        bindings.iter().enumerate().for_each(|(i, binding)| {
+                            if ui
+                                .button(binding.describe())
+                                .on_hover_text("Click to remove")
+                                .clicked()
+                            {
+                                removed = Some(i);
+                            }
+                        });
+                        if let Some(i) = removed {
+                            bindings.remove(i);
+                            changed = true;
+                        }
+                        if window.capturing == Some(*action) {
+                            if ui.button("Press an input...").clicked() {
+                                window.capturing = None;
+                            }
+                        } else if ui.button("+").clicked() {
+                            window.capturing = Some(*action);
+                        }
+                    });
+                    ui.end_row();
+                });
+            });
+            if ui.button("Reset to defaults").clicked() {
+                *bindings = Bindings::defaults();
+                changed = true;
+            }
+        });
+    if changed {
+        bindings.save();
+    }
+    if !open {
+        window.open = false;
+        window.capturing = None;
+    }
+}
+
+const STICK_AXES: [GamepadAxisType; 6] = [
+    GamepadAxisType::LeftStickX,
+    GamepadAxisType::LeftStickY,
+    GamepadAxisType::LeftZ,
+    GamepadAxisType::RightStickX,
+    GamepadAxisType::RightStickY,
+    GamepadAxisType::RightZ,
+];
+
+/// Binds the next pressed input to the action picked in the controls window.
+#[allow(clippy::too_many_arguments)]
+fn capture_binding(
+    mut egui_context: ResMut<EguiContext>,
+    mut window: ResMut<ControlsWindow>,
+    mut bindings: ResMut<Bindings>,
+    keys: Res<Input<KeyCode>>,
+    mouse: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    buttons: Res<Input<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+) {
+    let Some(action) = window.capturing else {
+        return;
+    };
+    // Clicks on the window itself are not bindings
+    let mouse = (!egui_context.ctx_mut().is_pointer_over_area())
+        .then(|| mouse.get_just_pressed().next().copied())
+        .flatten();
+    let binding = keys
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| mouse.map(Binding::Mouse))
+        .or_else(|| {
+            buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::Button(button.button_type))
+        })
+        .or_else(|| {
+            gamepads.iter().find_map(|gamepad| {
+                STICK_AXES.into_iter().find_map(|axis| {
+                    let value = axes.get(GamepadAxis::new(*gamepad, axis))?;
+                    (value.abs() > 0.7).then_some(Binding::Axis {
+                        axis,
+                        positive: value > 0.,
+                    })
+                })
+            })
+        });
+    if let Some(binding) = binding {
+        let list = bindings.0.entry(action).or_default();
+        if !list.contains(&binding) {
+            list.push(binding);
+        }
+        bindings.save();
+        window.capturing = None;
+    }
+}
+
+pub struct ControlsPlugin;
+
+impl Plugin for ControlsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bindings>()
+            .init_resource::<Actions>()
+            .init_resource::<ControlsWindow>()
+            .init_resource::<VirtualCursor>()
+            .add_system_to_stage(CoreStage::PreUpdate, update_actions.after(InputSystem))
+            .add_system_to_stage(CoreStage::PreUpdate, capture_binding.after(update_actions))
+            .add_system_to_stage(CoreStage::PreUpdate, move_cursor.after(update_actions))
+            .add_system(controls_window);
+    }
+}
@@ -1,20 +1,29 @@
 mod collision_groups;
+mod controls;
 mod map;
 mod nailgun;
 mod packages;
 mod player;
+mod run;
 mod utils;
 
 use bevy::{
+    asset::AssetServerSettings,
     prelude::*,
     render::texture::{ImageSampler, ImageSettings},
 };
+// Used by the debug plugins and systems commented out in `main`
+#[allow(unused_imports)]
 use bevy_editor_pls::prelude::*;
+use bevy_inspector_egui::bevy_egui::EguiPlugin;
+#[allow(unused_imports)]
 use bevy_rapier2d::{prelude::*, render::RapierDebugRenderPlugin};
-use map::chunk::ChunkPlugin;
+use controls::ControlsPlugin;
+use map::{chunk::ChunkPlugin, parallax::ParallaxPlugin};
 use nailgun::ToolPlugin;
 use packages::PackagePlugin;
 use player::PlayerPlugin;
+use run::RunPlugin;
 
 fn main() {
     App::new()
@@ -26,12 +35,21 @@ fn main() {
         .insert_resource(ImageSettings {
             default_sampler: ImageSampler::nearest_descriptor(),
         })
+        .insert_resource(AssetServerSettings {
+            // Preset files are hot reloaded in debug builds, there is no file watcher on the web
+            watch_for_changes: cfg!(debug_assertions) && !cfg!(target_arch = "wasm32"),
+            ..default()
+        })
         .insert_resource(ClearColor(Color::rgb(0.53, 0.81, 0.92)))
         .add_plugins(DefaultPlugins)
+        .add_plugin(EguiPlugin)
         //.add_plugin(EditorPlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.0))
         //.add_plugin(RapierDebugRenderPlugin::default())
+        .add_plugin(ControlsPlugin)
+        .add_plugin(RunPlugin)
         .add_plugin(ChunkPlugin)
+        .add_plugin(ParallaxPlugin)
         .add_plugin(PlayerPlugin)
         .add_plugin(PackagePlugin)
         .add_plugin(ToolPlugin)
@@ -41,6 +59,7 @@ fn main() {
         .run();
 }
 
+#[allow(dead_code)]
 fn toggle_debug_render(
     mut render: ResMut<DebugRenderContext>,
     keyboard_input: Res<Input<KeyCode>>,
@@ -50,6 +69,7 @@ fn toggle_debug_render(
     }
 }
 
+#[allow(dead_code)]
 fn debug_init(mut render: ResMut<DebugRenderContext>) {
     render.enabled = false;
 }
@@ -0,0 +1,295 @@
+use bevy::prelude::*;
+
+use super::{
+    seed::{streams, WorldSeed},
+    terrain::TerrainGenerator,
+    texture::TexturePattern,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Grassland,
+    Desert,
+    Snow,
+    Mud,
+    Rocky,
+}
+
+pub const BIOMES: [Biome; 5] = [
+    Biome::Grassland,
+    Biome::Desert,
+    Biome::Snow,
+    Biome::Mud,
+    Biome::Rocky,
+];
+
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeProperties {
+    pub surface_color: Color,
+    pub soil_color: Color,
+    pub rock_color: Color,
+    pub surface_texture: TexturePattern,
+    pub soil_texture: TexturePattern,
+    pub friction: f32,
+    pub restitution: f32,
+    pub roughness: f32,
+    /// Multipliers applied on top of the preset chances, by package name.
+    pub package_weights: &'static [(&'static str, u32)],
+}
+
+impl Biome {
+    pub fn properties(&self) -> BiomeProperties {
+        match self {
+            Biome::Grassland => BiomeProperties {
+                surface_color: Color::rgb(0.3, 1., 0.3),
+                soil_color: Color::rgb(0.5, 0.3, 0.3),
+                rock_color: Color::rgb(0.4, 0.4, 0.42),
+                surface_texture: TexturePattern {
+                    cells: (16, 1),
+                    octaves: 3,
+                    contrast: 0.5,
+                    bands: 0.,
+                    shade: 0.35,
+                    seed: 1,
+                },
+                soil_texture: TexturePattern {
+                    cells: (4, 4),
+                    octaves: 3,
+                    contrast: 0.4,
+                    bands: 0.1,
+                    shade: 0.,
+                    seed: 2,
+                },
+                friction: 1.,
+                restitution: 0.,
+                roughness: 1.,
+                package_weights: &[],
+            },
+            Biome::Desert => BiomeProperties {
+                surface_color: Color::rgb(0.95, 0.85, 0.5),
+                soil_color: Color::rgb(0.8, 0.6, 0.35),
+                rock_color: Color::rgb(0.6, 0.45, 0.3),
+                surface_texture: TexturePattern {
+                    cells: (8, 2),
+                    octaves: 2,
+                    contrast: 0.2,
+                    bands: 0.3,
+                    shade: 0.1,
+                    seed: 3,
+                },
+                soil_texture: TexturePattern {
+                    cells: (4, 8),
+                    octaves: 2,
+                    contrast: 0.2,
+                    bands: 0.5,
+                    shade: 0.,
+                    seed: 4,
+                },
+                friction: 0.7,
+                restitution: 0.,
+                roughness: 0.7,
+                package_weights: &[("Beach Ball", 3)],
+            },
+            Biome::Snow => BiomeProperties {
+                surface_color: Color::rgb(0.95, 0.97, 1.),
+                soil_color: Color::rgb(0.6, 0.7, 0.85),
+                rock_color: Color::rgb(0.5, 0.55, 0.65),
+                surface_texture: TexturePattern {
+                    cells: (4, 1),
+                    octaves: 2,
+                    contrast: 0.1,
+                    bands: 0.,
+                    shade: 0.2,
+                    seed: 5,
+                },
+                soil_texture: TexturePattern {
+                    cells: (3, 3),
+                    octaves: 2,
+                    contrast: 0.15,
+                    bands: 0.05,
+                    shade: 0.,
+                    seed: 6,
+                },
+                friction: 0.15,
+                restitution: 0.05,
+                roughness: 0.8,
+                package_weights: &[("Ice Cube", 4)],
+            },
+            Biome::Mud => BiomeProperties {
+                surface_color: Color::rgb(0.4, 0.3, 0.15),
+                soil_color: Color::rgb(0.3, 0.2, 0.1),
+                rock_color: Color::rgb(0.25, 0.2, 0.15),
+                surface_texture: TexturePattern {
+                    cells: (6, 1),
+                    octaves: 3,
+                    contrast: 0.4,
+                    bands: 0.,
+                    shade: 0.3,
+                    seed: 7,
+                },
+                soil_texture: TexturePattern {
+                    cells: (2, 2),
+                    octaves: 4,
+                    contrast: 0.5,
+                    bands: 0.,
+                    shade: 0.,
+                    seed: 8,
+                },
+                friction: 0.5,
+                restitution: 0.,
+                roughness: 0.6,
+                package_weights: &[("Wooden Crate", 3)],
+            },
+            Biome::Rocky => BiomeProperties {
+                surface_color: Color::rgb(0.55, 0.55, 0.55),
+                soil_color: Color::rgb(0.35, 0.35, 0.38),
+                rock_color: Color::rgb(0.3, 0.3, 0.33),
+                surface_texture: TexturePattern {
+                    cells: (12, 2),
+                    octaves: 4,
+                    contrast: 0.6,
+                    bands: 0.,
+                    shade: 0.2,
+                    seed: 9,
+                },
+                soil_texture: TexturePattern {
+                    cells: (8, 8),
+                    octaves: 4,
+                    contrast: 0.7,
+                    bands: 0.,
+                    shade: 0.,
+                    seed: 10,
+                },
+                friction: 0.9,
+                restitution: 0.3,
+                roughness: 1.6,
+                package_weights: &[("Bowling Ball", 3)],
+            },
+        }
+    }
+
+    pub fn package_weight(&self, name: &str) -> u32 {
+        self.properties()
+            .package_weights
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map_or(1, |(_, w)| *w)
+    }
+}
+
+/// Two neighbouring biomes and how far the transition between them went.
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeSample {
+    pub from: Biome,
+    pub to: Biome,
+    pub t: f32,
+    /// Derivative of `t` with respect to `x`.
+    pub dt: f32,
+}
+
+impl BiomeSample {
+    pub fn dominant(&self) -> Biome {
+        match self.t < 0.5 {
+            true => self.from,
+            false => self.to,
+        }
+    }
+
+    pub fn mix(&self, f: impl Fn(&BiomeProperties) -> f32) -> f32 {
+        let (a, b) = (f(&self.from.properties()), f(&self.to.properties()));
+        a + (b - a) * self.t
+    }
+
+    pub fn mix_color(&self, f: impl Fn(&BiomeProperties) -> Color) -> Color {
+        let (a, b) = (f(&self.from.properties()), f(&self.to.properties()));
+        Color::from(Vec4::from(a).lerp(Vec4::from(b), self.t))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeConfig {
+    pub region_size: f32,
+    pub blend_size: f32,
+}
+
+impl Default for BiomeConfig {
+    fn default() -> Self {
+        Self {
+            region_size: 8192.,
+            blend_size: 1024.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BiomeMap {
+    seed: WorldSeed,
+    config: BiomeConfig,
+}
+
+impl BiomeMap {
+    pub fn new(seed: WorldSeed, config: BiomeConfig) -> Self {
+        Self { seed, config }
+    }
+
+    /// The start and everything behind it is grassland, the rest is picked from the seed.
+    pub fn region(&self, i: i64) -> Biome {
+        match i {
+            i if i <= 0 => Biome::Grassland,
+            _ => BIOMES[(self.seed.hash_at(streams::BIOMES, i) % BIOMES.len() as u64) as usize],
+        }
+    }
+
+    pub fn sample(&self, x: f32) -> BiomeSample {
+        let size = self.config.region_size;
+        let half_blend = self.config.blend_size / 2.;
+        let i = (x / size).floor();
+        let local = x - i * size;
+        let i = i as i64;
+        let (from, to, s) = if local < half_blend {
+            (self.region(i - 1), self.region(i), local + half_blend)
+        } else if local > size - half_blend {
+            (
+                self.region(i),
+                self.region(i + 1),
+                local - size + half_blend,
+            )
+        } else {
+            let biome = self.region(i);
+            return BiomeSample {
+                from: biome,
+                to: biome,
+                t: 0.,
+                dt: 0.,
+            };
+        };
+        let s = s / self.config.blend_size;
+        BiomeSample {
+            from,
+            to,
+            t: s * s * (3. - 2. * s),
+            dt: 6. * s * (1. - s) / self.config.blend_size,
+        }
+    }
+}
+
+/// Scales the wrapped terrain by the roughness of the biome it passes through.
+#[derive(Debug)]
+pub struct BiomeTerrain {
+    pub inner: Box<dyn TerrainGenerator>,
+    pub map: BiomeMap,
+}
+
+impl TerrainGenerator for BiomeTerrain {
+    fn height(&self, x: f32) -> f32 {
+        self.inner.height(x) * self.map.sample(x).mix(|p| p.roughness)
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let sample = self.map.sample(x);
+        let roughness = sample.mix(|p| p.roughness);
+        let droughness =
+            (sample.to.properties().roughness - sample.from.properties().roughness) * sample.dt;
+        self.inner.slope(x) * roughness + self.inner.height(x) * droughness
+    }
+}
@@ -0,0 +1,206 @@
+use std::{collections::VecDeque, mem::size_of};
+
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    packages::{damage::Durability, presets::Package},
+    utils::{quat::rot_z, storage},
+};
+
+use super::{
+    chunk::ChunkData,
+    features::{BridgePlank, ChunkMember},
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropState {
+    pub feature: usize,
+    pub index: u32,
+    pub position: Vec2,
+    pub angle: f32,
+    pub broken: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageState {
+    pub preset: String,
+    pub position: Vec2,
+    pub angle: f32,
+    #[serde(default)]
+    pub durability: Option<f32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChunkState {
+    pub data: ChunkData,
+    pub props: Vec<PropState>,
+    pub packages: Vec<PackageState>,
+}
+
+impl ChunkState {
+    fn size(&self) -> usize {
+        size_of::<Self>()
+            + self.data.size()
+            + self.props.len() * size_of::<PropState>()
+            + self
+                .packages
+                .iter()
+                .map(|p| size_of::<PackageState>() + p.preset.len())
+                .sum::<usize>()
+    }
+}
+
+#[derive(Debug)]
+pub struct ChunkCacheConfig {
+    pub budget: usize,
+}
+
+impl Default for ChunkCacheConfig {
+    fn default() -> Self {
+        Self {
+            budget: 8 * 1024 * 1024,
+        }
+    }
+}
+
+/// State of unloaded chunks, the least recently stored ones are dropped once over budget
+/// and get generated from the seed again.
+#[derive(Debug, Default)]
+pub struct ChunkCache {
+    chunks: HashMap<i32, ChunkState>,
+    order: VecDeque<i32>,
+    size: usize,
+}
+
+impl ChunkCache {
+    pub fn insert(&mut self, i: i32, state: ChunkState, budget: usize) {
+        self.take(i);
+        self.size += state.size();
+        self.chunks.insert(i, state);
+        self.order.push_back(i);
+        while self.size > budget {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    if let Some(state) = self.chunks.remove(&oldest) {
+                        self.size -= state.size();
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn take(&mut self, i: i32) -> Option<ChunkState> {
+        let state = self.chunks.remove(&i)?;
+        self.size -= state.size();
+        self.order.retain(|o| *o != i);
+        Some(state)
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&i32, &ChunkState)> {
+        self.chunks.iter()
+    }
+}
+
+/// Sent when a restored chunk had loose packages lying around.
+#[derive(Debug)]
+pub struct RestorePackages(pub Vec<PackageState>);
+
+/// Collects the dynamic parts of a loaded chunk.
+#[derive(SystemParam)]
+pub struct ChunkSnapshot<'w, 's> {
+    planks: Query<
+        'w,
+        's,
+        (
+            &'static ChunkMember,
+            &'static BridgePlank,
+            &'static Transform,
+        ),
+    >,
+    packages: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Transform,
+            &'static Package,
+            Option<&'static Durability>,
+        ),
+    >,
+}
+
+impl<'w, 's> ChunkSnapshot<'w, 's> {
+    /// Returns the state of chunk `i` spanning `[min, max)` and the packages it now owns.
+    pub fn take(&self, i: i32, data: &ChunkData, min: f32, max: f32) -> (ChunkState, Vec<Entity>) {
+        let props = self
+            .planks
+            .iter()
+            .filter(|(member, _, _)| member.0 == i)
+            .map(|(_, plank, transform)| PropState {
+                feature: plank.feature,
+                index: plank.index,
+                position: transform.translation.truncate(),
+                angle: rot_z(transform.rotation),
+                broken: plank.broken,
+            })
+            .collect();
+        let (packages, entities) = self
+            .packages
+            .iter()
+            .filter(|(_, transform, ..)| (min..max).contains(&transform.translation.x))
+            .map(|(entity, transform, package, durability)| {
+                let state = PackageState {
+                    preset: package.name.clone(),
+                    position: transform.translation.truncate(),
+                    angle: rot_z(transform.rotation),
+                    durability: durability.map(|d| d.current),
+                };
+                (state, entity)
+            })
+            .unzip();
+        let state = ChunkState {
+            data: data.clone(),
+            props,
+            packages,
+        };
+        (state, entities)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub seed: u64,
+    pub chunks: Vec<(i32, ChunkState)>,
+}
+
+/// A save waiting for the run it restarts to build the world again.
+#[derive(Debug, Default)]
+pub struct LoadedSave(pub Option<SaveGame>);
+
+pub const SAVE_KEY: &str = "flippingout_save";
+
+pub fn write_save(save: &SaveGame) {
+    let result = ron::to_string(save)
+        .map_err(|e| e.to_string())
+        .and_then(|text| storage::save(SAVE_KEY, &text));
+    if let Err(e) = result {
+        error!("Failed to save the game: {e}");
+    }
+}
+
+pub fn read_save() -> Option<SaveGame> {
+    let text = storage::load(SAVE_KEY)?;
+    match ron::from_str(&text) {
+        Ok(save) => Some(save),
+        Err(e) => {
+            error!("Failed to read the save game: {e}");
+            None
+        }
+    }
+}
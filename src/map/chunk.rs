@@ -1,107 +1,377 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
 use bevy::{
-    prelude::*, render::render_resource::PrimitiveTopology, sprite::MaterialMesh2dBundle,
-    utils::HashSet,
+    prelude::*,
+    render::render_resource::PrimitiveTopology,
+    sprite::MaterialMesh2dBundle,
+    tasks::AsyncComputeTaskPool,
+    utils::{HashMap, HashSet},
 };
-use bevy_rapier2d::{prelude::*, rapier::prelude::Vector};
-use itertools::{izip, repeat_n, Itertools};
-use rand::{distributions::Uniform, prelude::Distribution, rngs::SmallRng, *};
+use bevy_rapier2d::prelude::*;
+use itertools::{repeat_n, Itertools};
+use rand::{distributions::Uniform, prelude::Distribution};
+use serde::{Deserialize, Serialize};
 
-use crate::{collision_groups::*, utils::iter::IteratorExt};
+use crate::{
+    collision_groups::*,
+    controls::{Action, Actions},
+    run::GameState,
+    utils::iter::IteratorExt,
+};
+
+use super::{
+    biome::{Biome, BiomeConfig, BiomeMap, BiomeTerrain, BIOMES},
+    cache::{
+        read_save, write_save, ChunkCache, ChunkCacheConfig, ChunkSnapshot, ChunkState, LoadedSave,
+        PropState, RestorePackages, SaveGame,
+    },
+    difficulty::{AmplitudeRamp, DifficultyConfig, RampedCosineSum},
+    features::{
+        break_planks, spawn_feature, ChunkAnchor, ChunkMember, Feature, FeatureConfig, FeatureMap,
+        FeatureTerrain,
+    },
+    seed::{show_seed, streams, WorldSeed},
+    terrain::*,
+    texture::ROCK_TEXTURE,
+};
 
 #[derive(Debug, Component)]
 pub struct Chunk(i32);
 
+impl Chunk {
+    pub fn index(&self) -> i32 {
+        self.0
+    }
+}
+
 #[derive(Debug, Component)]
 pub struct Chunkloader;
 
+/// Everything a chunk is built from, kept so it can be rebuilt without sampling the generator.
+#[derive(Debug, Clone, Component, Serialize, Deserialize)]
+pub struct ChunkData {
+    /// Local x, height and slope at every probe, sorted by x.
+    samples: Vec<Vec3>,
+    features: Vec<Feature>,
+}
+
+impl ChunkData {
+    pub fn size(&self) -> usize {
+        self.samples.len() * std::mem::size_of::<Vec3>()
+            + self.features.len() * std::mem::size_of::<Feature>()
+    }
+}
+
 #[derive(Debug)]
 pub struct ChunkGenConfig {
+    terrain: TerrainKind,
     frequency_range: (f32, f32),
     phase_range: (f32, f32),
     amplitude_range: (f32, f32),
+    noise_octaves: Vec<Octave>,
+    runway: f32,
+    height_limit: f32,
+    difficulty: DifficultyConfig,
+    biomes: BiomeConfig,
+    features: FeatureConfig,
 }
 
 impl Default for ChunkGenConfig {
     fn default() -> Self {
         Self {
+            terrain: TerrainKind::default(),
             frequency_range: (0., 0.005),
             phase_range: (0., std::f32::consts::TAU),
             amplitude_range: (30., 36.),
+            noise_octaves: vec![
+                Octave {
+                    frequency: 0.0005,
+                    amplitude: 300.,
+                },
+                Octave {
+                    frequency: 0.002,
+                    amplitude: 80.,
+                },
+                Octave {
+                    frequency: 0.008,
+                    amplitude: 15.,
+                },
+            ],
+            runway: 2048.,
+            height_limit: 400.,
+            difficulty: DifficultyConfig::default(),
+            biomes: BiomeConfig::default(),
+            features: FeatureConfig::default(),
         }
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone)]
 pub struct ChunkGen {
-    frequencies: [f32; 10],
-    phases: [f32; 10],
-    amplitudes: [f32; 10],
+    generator: Arc<dyn TerrainGenerator>,
+    biomes: BiomeMap,
+    features: FeatureMap,
+}
+
+impl Default for ChunkGen {
+    fn default() -> Self {
+        Self {
+            generator: Arc::new(CosineSum::default()),
+            biomes: BiomeMap::new(WorldSeed(0), BiomeConfig::default()),
+            features: FeatureMap::new(WorldSeed(0), FeatureConfig::default()),
+        }
+    }
 }
 
 impl ChunkGen {
-    pub fn reset(&mut self, config: &ChunkGenConfig) {
-        let rng = SmallRng::from_entropy();
-        self.frequencies = Uniform::new(config.frequency_range.0, config.frequency_range.1)
+    /// Surface point and normal at `x`, for putting things down on the track.
+    pub fn probe(&self, x: f32) -> (Vec2, Vec2) {
+        let normal = Vec2::new(-self.slope(x), 1.).normalize();
+        (Vec2::new(x, self.height(x)), normal)
+    }
+
+    pub fn reset(&mut self, config: &ChunkGenConfig, seed: &WorldSeed) {
+        self.biomes = BiomeMap::new(*seed, config.biomes);
+        self.features = FeatureMap::new(*seed, config.features.clone());
+        let generator: Box<dyn TerrainGenerator> = match config.terrain {
+            TerrainKind::Hills => Box::new(Self::hills(config, seed)),
+            TerrainKind::Noise => Box::new(AmplitudeRamp {
+                inner: Box::new(Self::noise(config, seed, NoiseKind::Perlin)),
+                config: config.difficulty,
+            }),
+            TerrainKind::Dunes => Box::new(AmplitudeRamp {
+                inner: Box::new(Self::noise(config, seed, NoiseKind::Value)),
+                config: config.difficulty,
+            }),
+            TerrainKind::Mixed => Box::new(Clamp {
+                inner: Box::new(Blend {
+                    from: Box::new(Profile {
+                        points: vec![Vec2::new(-config.runway, 0.), Vec2::new(0., 0.)],
+                    }),
+                    to: Box::new(Add(
+                        Box::new(Self::hills(config, seed)),
+                        Box::new(Multiply(
+                            Box::new(Self::noise(config, seed, NoiseKind::Perlin)),
+                            Box::new(Blend {
+                                from: Box::new(Constant(0.)),
+                                to: Box::new(Constant(1.)),
+                                start: config.runway,
+                                end: config.runway * 4.,
+                            }),
+                        )),
+                    )),
+                    start: 0.,
+                    end: config.runway,
+                }),
+                min: -config.height_limit,
+                max: config.height_limit,
+            }),
+        };
+        self.generator = Arc::new(FeatureTerrain {
+            inner: Box::new(BiomeTerrain {
+                inner: generator,
+                map: self.biomes,
+            }),
+            map: self.features.clone(),
+        });
+    }
+
+    pub fn biomes(&self) -> &BiomeMap {
+        &self.biomes
+    }
+
+    pub fn features(&self) -> &FeatureMap {
+        &self.features
+    }
+
+    fn hills(config: &ChunkGenConfig, seed: &WorldSeed) -> RampedCosineSum {
+        let rng = seed.rng(streams::TERRAIN_FREQUENCIES);
+        let mut frequencies = Uniform::new(config.frequency_range.0, config.frequency_range.1)
             .sample_iter(rng)
-            .take_array();
+            .take_array::<10>();
+        // Harmonics are enabled in order as difficulty grows, so start with the gentle ones
+        frequencies.sort_by(f32::total_cmp);
 
-        let rng = SmallRng::from_entropy();
-        self.phases = Uniform::new(config.phase_range.0, config.phase_range.1)
+        let rng = seed.rng(streams::TERRAIN_PHASES);
+        let phases = Uniform::new(config.phase_range.0, config.phase_range.1)
             .sample_iter(rng)
-            .take_array();
+            .take_array::<10>();
 
-        let rng = SmallRng::from_entropy();
-        self.amplitudes = Uniform::new(config.amplitude_range.0, config.amplitude_range.1)
+        let rng = seed.rng(streams::TERRAIN_AMPLITUDES);
+        let amplitudes = Uniform::new(config.amplitude_range.0, config.amplitude_range.1)
             .sample_iter(rng)
-            .take_array();
+            .take_array::<10>();
+
+        RampedCosineSum {
+            base: CosineSum {
+                frequencies: frequencies.to_vec(),
+                phases: phases.to_vec(),
+                amplitudes: amplitudes.to_vec(),
+            },
+            config: config.difficulty,
+        }
     }
 
-    pub fn probe(&self, x: f32) -> f32 {
-        izip!(self.frequencies, self.phases, self.amplitudes)
-            .map(|(f, p, a)| (x * f + p).cos() * a)
-            .sum()
+    fn noise(config: &ChunkGenConfig, seed: &WorldSeed, kind: NoiseKind) -> LayeredNoise {
+        LayeredNoise {
+            kind,
+            seed: *seed,
+            stream: streams::TERRAIN_NOISE,
+            octaves: config.noise_octaves.clone(),
+        }
     }
+}
 
-    fn probe_derivative(&self, x: f32) -> f32 {
-        izip!(self.frequencies, self.phases, self.amplitudes)
-            .map(|(f, p, a)| (x * f + p).cos() * f * a)
-            .sum()
+impl TerrainGenerator for ChunkGen {
+    fn height(&self, x: f32) -> f32 {
+        self.generator.height(x)
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        self.generator.slope(x)
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChunkConfig {
+    /// Evenly spaced probes every chunk starts with, before subdivision.
     probes: u32,
+    /// Largest distance allowed between the terrain and its polyline.
+    max_error: f32,
+    /// How many times a probe interval can be halved.
+    max_depth: u32,
     x_size: f32,
     gen_distance: f32,
     rem_distance: f32,
+    /// World size of one surface texture tile, has to divide half a chunk so tiles line up.
+    surface_tile: f32,
+    soil_tile: f32,
+    /// Depth below the surface where rock starts, no rock layer if `None`.
+    rock_depth: Option<f32>,
+    /// Chunks handed to the task pool per frame.
+    jobs_per_frame: usize,
+    /// Finished chunks spawned into the world per frame.
+    spawns_per_frame: usize,
+}
+
+impl ChunkConfig {
+    pub fn x_size(&self) -> f32 {
+        self.x_size
+    }
 }
 
 impl Default for ChunkConfig {
     fn default() -> Self {
         Self {
-            probes: 33,
+            probes: 17,
+            max_error: 1.,
+            max_depth: 5,
             x_size: 1024.,
             gen_distance: 4096.,
             rem_distance: 8192.,
+            surface_tile: 128.,
+            soil_tile: 256.,
+            rock_depth: Some(160.),
+            jobs_per_frame: 2,
+            spawns_per_frame: 1,
         }
     }
 }
 
+/// Chunk whose samples, meshes and collider were built off the main thread.
+struct BuiltChunk {
+    i: i32,
+    id: u64,
+    x: f32,
+    data: ChunkData,
+    collider: Collider,
+    grass_mesh: Mesh,
+    earth_mesh: Mesh,
+    rock_mesh: Option<Mesh>,
+}
+
+#[derive(Debug)]
+struct ChunkJob {
+    id: u64,
+    cancelled: Arc<AtomicBool>,
+    /// Cached state the job was started from, handed back to the cache on cancellation.
+    cached: Option<ChunkState>,
+}
+
+/// Chunks being built on the async compute pool.
+/// Tasks are detached and push into `finished`, the wasm task pool can't be polled.
+#[derive(Default)]
+pub struct ChunkJobs {
+    jobs: HashMap<i32, ChunkJob>,
+    finished: Arc<Mutex<Vec<BuiltChunk>>>,
+    next_id: u64,
+}
+
+impl ChunkJobs {
+    fn start(&mut self, i: i32, cached: Option<ChunkState>, config: &ChunkConfig, gen: &ChunkGen) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let data = cached.as_ref().map(|state| state.data.clone());
+        let (config, gen) = (config.clone(), gen.clone());
+        let (finished, flag) = (self.finished.clone(), cancelled.clone());
+        AsyncComputeTaskPool::get()
+            .spawn(async move {
+                if flag.load(Ordering::Relaxed) {
+                    return;
+                }
+                let built = build_chunk(&config, &gen, data, i, id);
+                if !flag.load(Ordering::Relaxed) {
+                    finished.lock().unwrap().push(built);
+                }
+            })
+            .detach();
+        self.jobs.insert(
+            i,
+            ChunkJob {
+                id,
+                cancelled,
+                cached,
+            },
+        );
+    }
+
+    fn cancel(&mut self, i: i32) -> Option<ChunkState> {
+        let job = self.jobs.remove(&i)?;
+        job.cancelled.store(true, Ordering::Relaxed);
+        job.cached
+    }
+
+    pub fn clear(&mut self) {
+        self.jobs
+            .drain()
+            .for_each(|(_, job)| job.cancelled.store(true, Ordering::Relaxed));
+        self.finished.lock().unwrap().clear();
+    }
+}
+
 fn calculate_distance(a: &Transform, b: &Transform) -> f32 {
     (a.translation.x - b.translation.x).abs()
 }
 
-#[allow(clippy::type_complexity)]
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 fn remove_chunks(
     mut commands: Commands,
     config: Res<ChunkConfig>,
-    chunks: Query<(Entity, &Transform), (With<Chunk>, Without<Chunkloader>)>,
+    cache_config: Res<ChunkCacheConfig>,
+    mut cache: ResMut<ChunkCache>,
+    chunks: Query<(Entity, &Chunk, &ChunkData, &Transform), Without<Chunkloader>>,
     chunkloaders: Query<&Transform, (With<Chunkloader>, Without<Chunk>)>,
+    members: Query<(Entity, &ChunkMember)>,
+    snapshot: ChunkSnapshot,
 ) {
-    chunks
+    let half_size = config.x_size / 2.;
+    let removed = chunks
         .iter()
-        .filter_map(|(chunk_entity, chunk_transform)| {
+        .filter_map(|(chunk_entity, chunk, data, chunk_transform)| {
             let distance = chunkloaders
                 .iter()
                 .map(|chunkloader_transform| {
@@ -110,23 +380,62 @@ fn remove_chunks(
                 .reduce(f32::min)
                 .unwrap();
             if distance > config.rem_distance {
-                Some(chunk_entity)
+                let x = chunk_transform.translation.x;
+                let (state, packages) = snapshot.take(chunk.0, data, x - half_size, x + half_size);
+                cache.insert(chunk.0, state, cache_config.budget);
+                packages
+                    .into_iter()
+                    .for_each(|entity| commands.entity(entity).despawn_recursive());
+                commands.entity(chunk_entity).despawn_recursive();
+                Some(chunk.0)
             } else {
                 None
             }
         })
-        .for_each(|entity| commands.entity(entity).despawn_recursive());
+        .collect::<HashSet<_>>();
+
+    members
+        .iter()
+        .filter(|(_, member)| removed.contains(&member.0))
+        .for_each(|(entity, _)| commands.entity(entity).despawn_recursive());
 }
 
+/// Queues missing chunks nearest to a chunkloader first, and cancels jobs that went out of range.
+/// The chunk a chunkloader stands in is built right away so nothing falls through the world.
+#[allow(clippy::too_many_arguments)]
 fn generate_chunks(
     mut commands: Commands,
     config: Res<ChunkConfig>,
+    cache_config: Res<ChunkCacheConfig>,
     gen: Res<ChunkGen>,
+    mut cache: ResMut<ChunkCache>,
+    mut jobs: ResMut<ChunkJobs>,
     chunks: Query<&Chunk, (With<Chunk>, Without<Chunkloader>)>,
     chunkloaders: Query<&Transform, (With<Chunkloader>, Without<Chunk>)>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
+    material: Res<TerrainMaterial>,
     mut meshes: ResMut<Assets<Mesh>>,
+    mut restore: EventWriter<RestorePackages>,
 ) {
+    let distance = |i: i32| {
+        chunkloaders
+            .iter()
+            .map(|t| (i as f32 * config.x_size - t.translation.x).abs())
+            .reduce(f32::min)
+            .unwrap_or(f32::INFINITY)
+    };
+
+    let stale = jobs
+        .jobs
+        .keys()
+        .copied()
+        .filter(|i| distance(*i) > config.rem_distance)
+        .collect::<Vec<_>>();
+    stale.into_iter().for_each(|i| {
+        if let Some(state) = jobs.cancel(i) {
+            cache.insert(i, state, cache_config.budget);
+        }
+    });
+
     let mut missing = HashSet::new();
     chunkloaders.iter().for_each(|chunkloader_transform| {
         let min_i = ((chunkloader_transform.translation.x - config.gen_distance) / config.x_size)
@@ -140,144 +449,518 @@ fn generate_chunks(
         missing.remove(&chunk.0);
     });
 
-    missing.into_iter().for_each(|i| {
-        let x = i as f32 * config.x_size;
-        generate_chunk(
+    let mut missing = missing
+        .into_iter()
+        .filter(|i| !jobs.jobs.contains_key(i))
+        .map(|i| (i, distance(i)))
+        .collect::<Vec<_>>();
+    missing.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let (urgent, queued): (Vec<_>, Vec<_>) = missing
+        .into_iter()
+        .partition(|(_, d)| *d <= config.x_size / 2.);
+    urgent.into_iter().for_each(|(i, _)| {
+        let cached = cache.take(i);
+        let data = cached.as_ref().map(|state| state.data.clone());
+        let built = build_chunk(&config, &gen, data, i, 0);
+        spawn_built(
             &mut commands,
-            &mut materials,
+            &material,
             &mut meshes,
-            &config,
             &gen,
-            x,
-            i,
+            &mut restore,
+            built,
+            cached,
         );
     });
+    queued
+        .into_iter()
+        .take(config.jobs_per_frame)
+        .for_each(|(i, _)| {
+            let cached = cache.take(i);
+            jobs.start(i, cached, &config, &gen);
+        });
 }
 
-const GRASS_COLOR: Color = Color::rgba(0.3, 1., 0.3, 1.);
-const EARTH_COLOR: Color = Color::rgba(0.5, 0.3, 0.3, 1.);
+fn spawn_chunks(
+    mut commands: Commands,
+    config: Res<ChunkConfig>,
+    gen: Res<ChunkGen>,
+    mut jobs: ResMut<ChunkJobs>,
+    material: Res<TerrainMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut restore: EventWriter<RestorePackages>,
+) {
+    let finished = {
+        let mut finished = jobs.finished.lock().unwrap();
+        let n = finished.len().min(config.spawns_per_frame);
+        finished.drain(..n).collect::<Vec<_>>()
+    };
+    finished.into_iter().for_each(|built| {
+        // Results of cancelled or restarted jobs can still arrive
+        if !matches!(jobs.jobs.get(&built.i), Some(job) if job.id == built.id) {
+            return;
+        }
+        let job = jobs.jobs.remove(&built.i).unwrap();
+        spawn_built(
+            &mut commands,
+            &material,
+            &mut meshes,
+            &gen,
+            &mut restore,
+            built,
+            job.cached,
+        );
+    });
+}
 
-fn generate_chunk(
+fn build_chunk(
+    config: &ChunkConfig,
+    gen: &ChunkGen,
+    data: Option<ChunkData>,
+    i: i32,
+    id: u64,
+) -> BuiltChunk {
+    let x = i as f32 * config.x_size;
+    let data = data.unwrap_or_else(|| sample_chunk(config, gen, x));
+    let (collider, grass_mesh, earth_mesh, rock_mesh) =
+        generate_meshes(config, &data, gen.biomes(), x);
+    BuiltChunk {
+        i,
+        id,
+        x,
+        data,
+        collider,
+        grass_mesh,
+        earth_mesh,
+        rock_mesh,
+    }
+}
+
+fn spawn_built(
     commands: &mut Commands,
-    materials: &mut Assets<ColorMaterial>,
+    material: &TerrainMaterial,
     meshes: &mut Assets<Mesh>,
+    gen: &ChunkGen,
+    restore: &mut EventWriter<RestorePackages>,
+    built: BuiltChunk,
+    cached: Option<ChunkState>,
+) {
+    let props = match cached {
+        Some(state) => {
+            if !state.packages.is_empty() {
+                restore.send(RestorePackages(state.packages));
+            }
+            state.props
+        }
+        None => vec![],
+    };
+    generate_chunk(commands, material, meshes, gen, built, &props);
+}
+
+fn sample_chunk(config: &ChunkConfig, gen: &ChunkGen, x: f32) -> ChunkData {
+    let half_size = config.x_size / 2.;
+    let dx = config.x_size / (config.probes - 1) as f32;
+    let probe = |lx: f32| {
+        let gx = x + lx;
+        Vec3::new(lx, gen.height(gx), gen.slope(gx))
+    };
+    let mut samples = vec![probe(-half_size)];
+    (1..config.probes).for_each(|i| {
+        let b = probe(i as f32 * dx - half_size);
+        let a = *samples.last().unwrap();
+        subdivide(config, &probe, a, b, 0, &mut samples);
+        samples.push(b);
+    });
+    let features = gen
+        .features()
+        .starting_in(x - half_size, x + half_size)
+        .collect();
+    ChunkData { samples, features }
+}
+
+/// Pushes the probes between `a` and `b` wherever the segment strays too far from the terrain.
+/// The slope term catches bumps the midpoint alone can miss.
+fn subdivide(
     config: &ChunkConfig,
+    probe: &impl Fn(f32) -> Vec3,
+    a: Vec3,
+    b: Vec3,
+    depth: u32,
+    samples: &mut Vec<Vec3>,
+) {
+    if depth >= config.max_depth {
+        return;
+    }
+    let mid = probe((a.x + b.x) / 2.);
+    let error = (mid.y - (a.y + b.y) / 2.)
+        .abs()
+        .max((b.z - a.z).abs() * (b.x - a.x) / 8.);
+    if error > config.max_error {
+        subdivide(config, probe, a, mid, depth + 1, samples);
+        samples.push(mid);
+        subdivide(config, probe, mid, b, depth + 1, samples);
+    }
+}
+
+/// Materials for the layers of one biome, tinted by the biome vertex colours.
+#[derive(Debug, Clone)]
+pub struct TerrainTextures {
+    pub surface: Handle<ColorMaterial>,
+    pub soil: Handle<ColorMaterial>,
+    pub rock: Handle<ColorMaterial>,
+}
+
+/// Shared by all chunks.
+/// Bevy only applies vertex colours to textured materials, hence the blank texture in `plain`.
+#[derive(Debug)]
+pub struct TerrainMaterial {
+    plain: Handle<ColorMaterial>,
+    biomes: HashMap<Biome, TerrainTextures>,
+}
+
+impl FromWorld for TerrainMaterial {
+    fn from_world(world: &mut World) -> Self {
+        let mut images = world.resource_mut::<Assets<Image>>();
+        let blank = images.add(Image::default());
+        let rock = images.add(ROCK_TEXTURE.image(128, 128));
+        let biomes = BIOMES
+            .map(|biome| {
+                let properties = biome.properties();
+                let surface = images.add(properties.surface_texture.image(128, 16));
+                let soil = images.add(properties.soil_texture.image(128, 128));
+                (biome, [surface, soil, rock.clone()])
+            })
+            .to_vec();
+        let mut materials = world.resource_mut::<Assets<ColorMaterial>>();
+        let plain = materials.add(ColorMaterial::from(blank));
+        let biomes = biomes
+            .into_iter()
+            .map(|(biome, [surface, soil, rock])| {
+                let textures = TerrainTextures {
+                    surface: materials.add(ColorMaterial::from(surface)),
+                    soil: materials.add(ColorMaterial::from(soil)),
+                    rock: materials.add(ColorMaterial::from(rock)),
+                };
+                (biome, textures)
+            })
+            .collect();
+        Self { plain, biomes }
+    }
+}
+
+impl TerrainMaterial {
+    pub fn plain(&self) -> &Handle<ColorMaterial> {
+        &self.plain
+    }
+}
+
+fn generate_chunk(
+    commands: &mut Commands,
+    material: &TerrainMaterial,
+    meshes: &mut Assets<Mesh>,
     gen: &ChunkGen,
-    x: f32,
-    i: i32,
+    built: BuiltChunk,
+    props: &[PropState],
 ) {
-    let (collider, grass_mesh, earth_mesh) = generate_meshes(meshes, config, gen, x);
+    let BuiltChunk {
+        i,
+        x,
+        data,
+        collider,
+        grass_mesh,
+        earth_mesh,
+        rock_mesh,
+        ..
+    } = built;
+    let biome = gen.biomes().sample(x);
+    let textures = &material.biomes[&biome.dominant()];
 
-    commands
+    let chunk = commands
         .spawn_bundle(MaterialMesh2dBundle {
-            mesh: grass_mesh.into(),
-            material: materials.add(ColorMaterial::from(GRASS_COLOR)),
+            mesh: meshes.add(grass_mesh).into(),
+            material: textures.surface.clone(),
             transform: Transform::from_xyz(x, 0., -1.),
             ..Default::default()
         })
         .insert(RigidBody::Fixed)
         .insert(collider)
+        .insert(Friction {
+            coefficient: biome.mix(|p| p.friction),
+            combine_rule: CoefficientCombineRule::Average,
+        })
+        .insert(Restitution {
+            coefficient: biome.mix(|p| p.restitution),
+            combine_rule: CoefficientCombineRule::Average,
+        })
         .insert(CollisionGroups::new(SOLID_TERRAIN, LOOSE_ITEMS | PLAYER))
         .insert(Chunk(i))
         .with_children(|b| {
             b.spawn_bundle(MaterialMesh2dBundle {
-                mesh: earth_mesh.into(),
-                material: materials.add(ColorMaterial::from(EARTH_COLOR)),
+                mesh: meshes.add(earth_mesh).into(),
+                material: textures.soil.clone(),
                 transform: Transform::from_xyz(0., 0., -1.),
                 ..Default::default()
             });
-        });
+            if let Some(rock_mesh) = rock_mesh {
+                b.spawn_bundle(MaterialMesh2dBundle {
+                    mesh: meshes.add(rock_mesh).into(),
+                    material: textures.rock.clone(),
+                    transform: Transform::from_xyz(0., 0., -1.),
+                    ..Default::default()
+                });
+            }
+        })
+        .id();
+
+    let anchor = ChunkAnchor {
+        entity: chunk,
+        x,
+        index: i,
+    };
+    data.features.iter().enumerate().for_each(|(n, feature)| {
+        spawn_feature(
+            commands,
+            meshes,
+            &material.plain,
+            gen,
+            feature,
+            n,
+            props,
+            anchor,
+        );
+    });
+    commands.entity(chunk).insert(data);
+}
+
+fn strip_mesh(
+    top: &[[f32; 3]],
+    bottom: &[[f32; 3]],
+    normals: (&[[f32; 3]], &[[f32; 3]]),
+    uvs: (&[[f32; 2]], &[[f32; 2]]),
+    colors: &[[f32; 4]],
+) -> Mesh {
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleStrip);
+    let positions = top.iter().interleave(bottom).copied().collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    let normals = normals
+        .0
+        .iter()
+        .interleave(normals.1)
+        .copied()
+        .collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    let uvs = uvs.0.iter().interleave(uvs.1).copied().collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    let colors = colors
+        .iter()
+        .flat_map(|c| repeat_n(*c, 2))
+        .collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh
 }
 
+/// Surface uvs follow the arc length, stretched to a whole number of tiles per chunk so they
+/// line up with the neighbours. Soil and rock are mapped in world space.
 fn generate_meshes(
-    meshes: &mut Assets<Mesh>,
     config: &ChunkConfig,
-    gen: &ChunkGen,
+    data: &ChunkData,
+    biomes: &BiomeMap,
     x: f32,
-) -> (Collider, Handle<Mesh>, Handle<Mesh>) {
+) -> (Collider, Mesh, Mesh, Option<Mesh>) {
     let offset = 10.;
-    let probes = config.probes as usize;
-    let half_size = config.x_size / 2.;
-    let dx = config.x_size / (config.probes - 1) as f32;
-    let (y, pos, norm, pos2): (Vec<_>, Vec<_>, Vec<_>, Vec<_>) = (0..config.probes)
-        .map(|i| {
-            let lx = i as f32 * dx;
-            let gx = x + lx;
-            let y = gen.probe(gx);
-            let pos = [lx - half_size, y, 0.];
-            let norm =
-                Vec2::from_angle(gen.probe_derivative(gx).atan2(dx) + std::f32::consts::FRAC_PI_2);
+    let bottom = -1000.;
+    let (line, pos, norm, pos2, biome): (Vec<_>, Vec<_>, Vec<_>, Vec<_>, Vec<_>) = data
+        .samples
+        .iter()
+        .map(|sample| {
+            let pos = [sample.x, sample.y, 0.];
+            let norm = Vec2::from_angle(sample.z.atan() + std::f32::consts::FRAC_PI_2);
             let norm = [norm.x, norm.y, 0.];
             let pos2 = Vec2::new(pos[0] - norm[0] * offset, pos[1] - norm[1] * offset);
             let pos2 = [pos2.x, pos2.y, 0.];
-            (y, pos, norm, pos2)
+            (
+                sample.truncate(),
+                pos,
+                norm,
+                pos2,
+                biomes.sample(x + sample.x),
+            )
         })
         .multiunzip();
+    let down = repeat_n([0., -1., 0.], pos.len()).collect::<Vec<_>>();
+    let planar = |p: &[f32; 3]| [(x + p[0]) / config.soil_tile, -p[1] / config.soil_tile];
 
-    let mut grass_mesh = Mesh::new(PrimitiveTopology::TriangleStrip);
-    let positions = pos
+    let arc = line
         .iter()
-        .interleave(pos2.iter())
-        .copied()
+        .scan(None, |previous: &mut Option<Vec2>, p| {
+            let length = previous.map_or(0., |q| p.distance(q));
+            *previous = Some(*p);
+            Some(length)
+        })
+        .scan(0., |total, length| {
+            *total += length;
+            Some(*total)
+        })
         .collect::<Vec<_>>();
-    grass_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    let normals = norm
+    let total = arc.last().copied().unwrap_or(0.).max(f32::EPSILON);
+    let tiles = (total / config.surface_tile).round().max(1.);
+    let start = (x - config.x_size / 2.) / config.surface_tile;
+    let u = arc
         .iter()
-        .copied()
-        .interleave(norm.iter().map(|x| [-x[0], -x[1], 0.]))
+        .map(|s| start + s / total * tiles)
         .collect::<Vec<_>>();
-    grass_mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    let uvs = repeat_n([0., 0.], 2 * probes).collect::<Vec<_>>();
-    grass_mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    let grass_mesh = meshes.add(grass_mesh);
-
-    let mut earth_mesh = Mesh::new(PrimitiveTopology::TriangleStrip);
-    let positions = pos2
+    let surface_colors = biome
         .iter()
-        .copied()
-        .interleave(pos2.iter().map(|p| [p[0], -1000., 0.]))
+        .map(|b| b.mix_color(|p| p.surface_color).as_linear_rgba_f32())
         .collect::<Vec<_>>();
-    earth_mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
-    let normals = norm
+    let grass_mesh = strip_mesh(
+        &pos,
+        &pos2,
+        (
+            &norm,
+            &norm.iter().map(|n| [-n[0], -n[1], 0.]).collect::<Vec<_>>(),
+        ),
+        (
+            &u.iter().map(|u| [*u, 0.]).collect::<Vec<_>>(),
+            &u.iter().map(|u| [*u, 1.]).collect::<Vec<_>>(),
+        ),
+        &surface_colors,
+    );
+
+    let rock = config.rock_depth.map(|depth| {
+        pos2.iter()
+            .map(|p| [p[0], (p[1] - depth).max(bottom), 0.])
+            .collect::<Vec<_>>()
+    });
+    let earth_bottom = match &rock {
+        Some(rock) => rock.clone(),
+        None => pos2.iter().map(|p| [p[0], bottom, 0.]).collect(),
+    };
+    let soil_colors = biome
         .iter()
-        .copied()
-        .interleave(repeat_n([0., -1., 0.], probes))
+        .map(|b| b.mix_color(|p| p.soil_color).as_linear_rgba_f32())
         .collect::<Vec<_>>();
-    earth_mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
-    let uvs = repeat_n([0., 0.], 2 * probes).collect::<Vec<_>>();
-    earth_mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
-    let earth_mesh = meshes.add(earth_mesh);
+    let earth_mesh = strip_mesh(
+        &pos2,
+        &earth_bottom,
+        (&norm, &down),
+        (
+            &pos2.iter().map(planar).collect::<Vec<_>>(),
+            &earth_bottom.iter().map(planar).collect::<Vec<_>>(),
+        ),
+        &soil_colors,
+    );
 
-    let scale = config.x_size;
-    let collider = Collider::heightfield(y, Vector::new(scale, 1.));
+    let rock_mesh = rock.map(|rock| {
+        let rock_bottom = rock.iter().map(|p| [p[0], bottom, 0.]).collect::<Vec<_>>();
+        let rock_colors = biome
+            .iter()
+            .map(|b| b.mix_color(|p| p.rock_color).as_linear_rgba_f32())
+            .collect::<Vec<_>>();
+        strip_mesh(
+            &rock,
+            &rock_bottom,
+            (&norm, &down),
+            (
+                &rock.iter().map(planar).collect::<Vec<_>>(),
+                &rock_bottom.iter().map(planar).collect::<Vec<_>>(),
+            ),
+            &rock_colors,
+        )
+    });
 
-    (collider, grass_mesh, earth_mesh)
+    let collider = Collider::polyline(line, None);
+
+    (collider, grass_mesh, earth_mesh, rock_mesh)
 }
 
-fn init(mut gen: ResMut<ChunkGen>, config: Res<ChunkGenConfig>) {
-    gen.reset(&config);
+/// Every run starts on a fresh track for the current seed.
+fn init(
+    mut gen: ResMut<ChunkGen>,
+    config: Res<ChunkGenConfig>,
+    seed: Res<WorldSeed>,
+    cache_config: Res<ChunkCacheConfig>,
+    mut cache: ResMut<ChunkCache>,
+    mut jobs: ResMut<ChunkJobs>,
+    mut loaded: ResMut<LoadedSave>,
+) {
+    gen.reset(&config, &seed);
+    cache.clear();
+    jobs.clear();
+    if let Some(save) = loaded.0.take() {
+        save.chunks
+            .into_iter()
+            .for_each(|(i, state)| cache.insert(i, state, cache_config.budget));
+    }
 }
 
 pub struct ChunkPlugin;
 
 impl Plugin for ChunkPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<ChunkGenConfig>()
+        app.init_resource::<WorldSeed>()
+            .init_resource::<ChunkGenConfig>()
             .init_resource::<ChunkGen>()
             .init_resource::<ChunkConfig>()
-            .add_startup_system(init)
-            .add_system(remove_chunks)
-            .add_system(generate_chunks)
-            .add_system(reset);
+            .init_resource::<TerrainMaterial>()
+            .init_resource::<ChunkCacheConfig>()
+            .init_resource::<ChunkCache>()
+            .init_resource::<ChunkJobs>()
+            .init_resource::<LoadedSave>()
+            .add_event::<RestorePackages>()
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(init))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(remove_chunks)
+                    .with_system(generate_chunks)
+                    // Spawned chunks only exist once the commands apply, until then the job
+                    // is what keeps `generate_chunks` from building the chunk again
+                    .with_system(spawn_chunks.after(generate_chunks))
+                    .with_system(break_planks)
+                    .with_system(save_load)
+                    .with_system(show_seed),
+            );
     }
 }
 
-fn reset(
+/// Saving stores the seed and the state of every chunk seen so far.
+/// Loading starts the run over on the saved world, chunks are rebuilt from the save as they get generated again.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+fn save_load(
     mut commands: Commands,
-    keyboard_input: Res<Input<KeyCode>>,
-    query: Query<Entity, With<Chunk>>,
+    actions: Res<Actions>,
+    config: Res<ChunkConfig>,
+    mut seed: ResMut<WorldSeed>,
+    cache: Res<ChunkCache>,
+    mut loaded: ResMut<LoadedSave>,
+    mut state: ResMut<State<GameState>>,
+    chunks: Query<(&Chunk, &ChunkData, &Transform)>,
+    snapshot: ChunkSnapshot,
+    entities: Query<Entity, (Without<Parent>, Without<Camera>)>,
 ) {
-    if keyboard_input.just_pressed(KeyCode::P) {
-        query.for_each(|e| commands.entity(e).despawn_recursive());
+    let half_size = config.x_size / 2.;
+    if actions.just_pressed(Action::Save) {
+        let loaded = chunks.iter().map(|(chunk, data, transform)| {
+            let x = transform.translation.x;
+            (
+                chunk.0,
+                snapshot.take(chunk.0, data, x - half_size, x + half_size).0,
+            )
+        });
+        let cached = cache.iter().map(|(i, state)| (*i, state.clone()));
+        write_save(&SaveGame {
+            seed: seed.0,
+            chunks: loaded.chain(cached).collect(),
+        });
+    } else if actions.just_pressed(Action::Load) {
+        if let Some(save) = read_save() {
+            // Torn down and restarted like a retry, only on the saved seed
+            if state.restart().is_ok() {
+                *seed = WorldSeed(save.seed);
+                loaded.0 = Some(save);
+                entities.for_each(|e| commands.entity(e).despawn_recursive());
+            }
+        }
     }
 }
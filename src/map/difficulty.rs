@@ -0,0 +1,189 @@
+use itertools::izip;
+
+use crate::utils::launch::launch_option;
+
+use super::terrain::{CosineSum, TerrainGenerator};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CurveShape {
+    Linear,
+    EaseIn,
+    EaseOut,
+    SmoothStep,
+}
+
+impl Default for CurveShape {
+    fn default() -> Self {
+        launch_option("difficulty")
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or(Self::SmoothStep)
+    }
+}
+
+impl CurveShape {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "linear" => Some(Self::Linear),
+            "ease-in" => Some(Self::EaseIn),
+            "ease-out" => Some(Self::EaseOut),
+            "smoothstep" => Some(Self::SmoothStep),
+            _ => None,
+        }
+    }
+
+    /// Value and derivative for `t` in [0, 1].
+    fn apply(&self, t: f32) -> (f32, f32) {
+        match self {
+            CurveShape::Linear => (t, 1.),
+            CurveShape::EaseIn => (t * t, 2. * t),
+            CurveShape::EaseOut => (t * (2. - t), 2. - 2. * t),
+            CurveShape::SmoothStep => (t * t * (3. - 2. * t), 6. * t * (1. - t)),
+        }
+    }
+}
+
+/// Maps distance travelled to a difficulty in [0, 1].
+/// Every shape is continuous, so terrain sampled at the same `x` from two neighbouring chunks
+/// always agrees, no matter how far apart in time they were generated.
+#[derive(Debug, Clone, Copy)]
+pub struct DifficultyCurve {
+    pub shape: CurveShape,
+    pub start: f32,
+    pub length: f32,
+}
+
+impl Default for DifficultyCurve {
+    fn default() -> Self {
+        Self {
+            shape: CurveShape::default(),
+            start: 2048.,
+            length: 100_000.,
+        }
+    }
+}
+
+impl DifficultyCurve {
+    /// Difficulty and its derivative with respect to `x`.
+    pub fn sample(&self, x: f32) -> (f32, f32) {
+        let t = (x - self.start) / self.length;
+        if t <= 0. {
+            (0., 0.)
+        } else if t >= 1. {
+            (1., 0.)
+        } else {
+            let (d, dd) = self.shape.apply(t);
+            (d, dd / self.length)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DifficultyConfig {
+    pub curve: DifficultyCurve,
+    pub amplitude_scale: (f32, f32),
+    pub frequency_scale: (f32, f32),
+    pub harmonics: (f32, f32),
+}
+
+impl Default for DifficultyConfig {
+    fn default() -> Self {
+        Self {
+            curve: DifficultyCurve::default(),
+            amplitude_scale: (1., 2.5),
+            frequency_scale: (1., 2.),
+            harmonics: (4., 10.),
+        }
+    }
+}
+
+fn lerp(range: (f32, f32), d: f32) -> (f32, f32) {
+    (range.0 + (range.1 - range.0) * d, range.1 - range.0)
+}
+
+/// Cosine sum whose amplitude, frequency and active harmonic count follow a difficulty curve.
+/// Frequency is changed by crossfading between the base and the scaled harmonics,
+/// scaling the phase directly would compress the whole track behind the player.
+#[derive(Debug)]
+pub struct RampedCosineSum {
+    pub base: CosineSum,
+    pub config: DifficultyConfig,
+}
+
+impl RampedCosineSum {
+    /// Weight of the `i`-th harmonic and its derivative with respect to difficulty.
+    fn harmonic_weight(&self, i: usize, d: f32) -> (f32, f32) {
+        let (active, dactive) = lerp(self.config.harmonics, d);
+        let w = active - i as f32;
+        if w <= 0. {
+            (0., 0.)
+        } else if w >= 1. {
+            (1., 0.)
+        } else {
+            (w, dactive)
+        }
+    }
+}
+
+impl TerrainGenerator for RampedCosineSum {
+    fn height(&self, x: f32) -> f32 {
+        let (d, _) = self.config.curve.sample(x);
+        let (amplitude, _) = lerp(self.config.amplitude_scale, d);
+        let (s0, s1) = self.config.frequency_scale;
+        izip!(
+            &self.base.frequencies,
+            &self.base.phases,
+            &self.base.amplitudes
+        )
+        .enumerate()
+        .map(|(i, (f, p, a))| {
+            let (w, _) = self.harmonic_weight(i, d);
+            let low = (x * f * s0 + p).cos();
+            let high = (x * f * s1 + p).cos();
+            (low * (1. - d) + high * d) * a * w
+        })
+        .sum::<f32>()
+            * amplitude
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let (d, dd) = self.config.curve.sample(x);
+        let (amplitude, damplitude) = lerp(self.config.amplitude_scale, d);
+        let (s0, s1) = self.config.frequency_scale;
+        let (sum, dsum) = izip!(
+            &self.base.frequencies,
+            &self.base.phases,
+            &self.base.amplitudes
+        )
+        .enumerate()
+        .map(|(i, (f, p, a))| {
+            let (w, dw) = self.harmonic_weight(i, d);
+            let (low, dlow) = ((x * f * s0 + p).cos(), -(x * f * s0 + p).sin() * f * s0);
+            let (high, dhigh) = ((x * f * s1 + p).cos(), -(x * f * s1 + p).sin() * f * s1);
+            let mix = low * (1. - d) + high * d;
+            let dmix = dlow * (1. - d) + dhigh * d + (high - low) * dd;
+            (mix * a * w, (dmix * w + mix * dw * dd) * a)
+        })
+        .fold((0., 0.), |acc, v| (acc.0 + v.0, acc.1 + v.1));
+        dsum * amplitude + sum * damplitude * dd
+    }
+}
+
+/// Scales any generator by the difficulty amplitude.
+#[derive(Debug)]
+pub struct AmplitudeRamp {
+    pub inner: Box<dyn TerrainGenerator>,
+    pub config: DifficultyConfig,
+}
+
+impl TerrainGenerator for AmplitudeRamp {
+    fn height(&self, x: f32) -> f32 {
+        let (d, _) = self.config.curve.sample(x);
+        self.inner.height(x) * lerp(self.config.amplitude_scale, d).0
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let (d, dd) = self.config.curve.sample(x);
+        let (amplitude, damplitude) = lerp(self.config.amplitude_scale, d);
+        self.inner.slope(x) * amplitude + self.inner.height(x) * damplitude * dd
+    }
+}
@@ -0,0 +1,424 @@
+use std::f32::consts::{FRAC_PI_2, PI};
+
+use bevy::{prelude::*, render::render_resource::PrimitiveTopology, sprite::MaterialMesh2dBundle};
+use bevy_rapier2d::prelude::*;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::collision_groups::*;
+
+use super::{
+    cache::PropState,
+    seed::{streams, WorldSeed},
+    terrain::TerrainGenerator,
+};
+
+/// Width of the steep walls of pits and cliffs.
+const EDGE: f32 = 16.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum FeatureKind {
+    Ramp {
+        length: f32,
+        height: f32,
+    },
+    Pit {
+        width: f32,
+        depth: f32,
+    },
+    Bridge {
+        width: f32,
+        depth: f32,
+        planks: u32,
+        strength: f32,
+    },
+    Cliff {
+        length: f32,
+        height: f32,
+    },
+    Loop {
+        radius: f32,
+    },
+}
+
+impl FeatureKind {
+    pub fn length(&self) -> f32 {
+        match *self {
+            FeatureKind::Ramp { length, .. } => length,
+            FeatureKind::Pit { width, .. } => width,
+            FeatureKind::Bridge { width, .. } => width,
+            FeatureKind::Cliff { length, .. } => length,
+            FeatureKind::Loop { radius } => radius * 4.,
+        }
+    }
+
+    /// Height added to the terrain `t` units into the feature, with its derivative.
+    fn offset(&self, t: f32) -> (f32, f32) {
+        match *self {
+            FeatureKind::Ramp { length, height } => {
+                let s = t / length;
+                (height * s * s, 2. * height * s / length)
+            }
+            FeatureKind::Pit { width, depth } | FeatureKind::Bridge { width, depth, .. } => {
+                trapezoid(t, width, EDGE, EDGE, -depth)
+            }
+            FeatureKind::Cliff { length, height } => {
+                trapezoid(t, length, length / 4., EDGE, height)
+            }
+            FeatureKind::Loop { .. } => (0., 0.),
+        }
+    }
+
+    /// How much the terrain is flattened to the feature's base height, with its derivative.
+    fn flatten(&self, t: f32) -> (f32, f32) {
+        match *self {
+            FeatureKind::Loop { radius } => trapezoid(t, radius * 4., radius, radius, 1.),
+            _ => (0., 0.),
+        }
+    }
+}
+
+/// Rises to `value` over `rise`, holds it, then falls back to zero over `fall`.
+fn trapezoid(t: f32, length: f32, rise: f32, fall: f32, value: f32) -> (f32, f32) {
+    if t < rise {
+        (value * t / rise, value / rise)
+    } else if t > length - fall {
+        (value * (length - t) / fall, -value / fall)
+    } else {
+        (value, 0.)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Feature {
+    pub x: f32,
+    pub kind: FeatureKind,
+}
+
+impl Feature {
+    pub fn end(&self) -> f32 {
+        self.x + self.kind.length()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FeatureConfig {
+    pub start: f32,
+    pub spacing: f32,
+    pub chance: f64,
+    pub kinds: Vec<(FeatureKind, u32)>,
+}
+
+impl Default for FeatureConfig {
+    fn default() -> Self {
+        Self {
+            start: 4096.,
+            spacing: 3072.,
+            chance: 0.6,
+            kinds: vec![
+                (
+                    FeatureKind::Ramp {
+                        length: 384.,
+                        height: 96.,
+                    },
+                    3,
+                ),
+                (
+                    FeatureKind::Pit {
+                        width: 192.,
+                        depth: 300.,
+                    },
+                    2,
+                ),
+                (
+                    FeatureKind::Bridge {
+                        width: 512.,
+                        depth: 400.,
+                        planks: 12,
+                        strength: 40.,
+                    },
+                    2,
+                ),
+                (
+                    FeatureKind::Cliff {
+                        length: 768.,
+                        height: 128.,
+                    },
+                    2,
+                ),
+                (FeatureKind::Loop { radius: 200. }, 1),
+            ],
+        }
+    }
+}
+
+/// Places features deterministically, one optional feature per `spacing` wide slot.
+#[derive(Debug, Clone)]
+pub struct FeatureMap {
+    seed: WorldSeed,
+    config: FeatureConfig,
+}
+
+impl FeatureMap {
+    pub fn new(seed: WorldSeed, config: FeatureConfig) -> Self {
+        Self { seed, config }
+    }
+
+    fn slot(&self, i: i64) -> Option<Feature> {
+        let slot_x = i as f32 * self.config.spacing;
+        if slot_x < self.config.start || self.config.kinds.is_empty() {
+            return None;
+        }
+        let mut rng = self.seed.rng_at(streams::FEATURES, i);
+        if !rng.gen_bool(self.config.chance) {
+            return None;
+        }
+        let dist = WeightedIndex::new(self.config.kinds.iter().map(|(_, w)| *w)).ok()?;
+        let kind = self.config.kinds[dist.sample(&mut rng)].0;
+        let slack = (self.config.spacing - kind.length() - EDGE * 2.).max(0.);
+        Some(Feature {
+            x: slot_x + EDGE + rng.gen_range(0. ..=slack),
+            kind,
+        })
+    }
+
+    pub fn at(&self, x: f32) -> Option<Feature> {
+        self.slot((x / self.config.spacing).floor() as i64)
+            .filter(|f| x >= f.x && x < f.end())
+    }
+
+    /// Features starting within `[min, max)`.
+    pub fn starting_in(&self, min: f32, max: f32) -> impl Iterator<Item = Feature> + '_ {
+        let first = (min / self.config.spacing).floor() as i64;
+        let last = (max / self.config.spacing).ceil() as i64;
+        (first..=last)
+            .filter_map(|i| self.slot(i))
+            .filter(move |f| f.x >= min && f.x < max)
+    }
+}
+
+/// Splices features into the wrapped terrain, everything is a function of world `x`
+/// so chunk seams still line up when a feature crosses them.
+#[derive(Debug)]
+pub struct FeatureTerrain {
+    pub inner: Box<dyn TerrainGenerator>,
+    pub map: FeatureMap,
+}
+
+impl TerrainGenerator for FeatureTerrain {
+    fn height(&self, x: f32) -> f32 {
+        let height = self.inner.height(x);
+        match self.map.at(x) {
+            Some(f) => {
+                let t = x - f.x;
+                let (w, _) = f.kind.flatten(t);
+                let anchor = self.inner.height(f.x + f.kind.length() / 2.);
+                height * (1. - w) + anchor * w + f.kind.offset(t).0
+            }
+            None => height,
+        }
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let slope = self.inner.slope(x);
+        match self.map.at(x) {
+            Some(f) => {
+                let t = x - f.x;
+                let (w, dw) = f.kind.flatten(t);
+                let height = self.inner.height(x);
+                let anchor = self.inner.height(f.x + f.kind.length() / 2.);
+                slope * (1. - w) + (anchor - height) * dw + f.kind.offset(t).1
+            }
+            None => slope,
+        }
+    }
+}
+
+/// Entities which are not children of a chunk but share its lifetime.
+#[derive(Debug, Component)]
+pub struct ChunkMember(pub i32);
+
+#[derive(Debug, Component)]
+pub struct BridgePlank {
+    pub feature: usize,
+    pub index: u32,
+    pub broken: bool,
+    strength: f32,
+}
+
+/// The terrain body features are attached to.
+#[derive(Debug, Clone, Copy)]
+pub struct ChunkAnchor {
+    pub entity: Entity,
+    pub x: f32,
+    pub index: i32,
+}
+
+const PLANK_COLOR: Color = Color::rgb(0.55, 0.35, 0.15);
+const LOOP_COLOR: Color = Color::rgb(0.45, 0.45, 0.5);
+
+/// Spawns the bodies that can't be expressed as terrain height.
+/// `props` holds the state of previously spawned bodies of this feature when it is restored.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_feature(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    material: &Handle<ColorMaterial>,
+    gen: &dyn TerrainGenerator,
+    feature: &Feature,
+    feature_index: usize,
+    props: &[PropState],
+    chunk: ChunkAnchor,
+) {
+    let ChunkAnchor {
+        entity: anchor,
+        x: chunk_x,
+        index: i,
+    } = chunk;
+    match feature.kind {
+        FeatureKind::Bridge {
+            width,
+            planks,
+            strength,
+            ..
+        } => {
+            let start = Vec2::new(feature.x, gen.height(feature.x));
+            let end = Vec2::new(feature.x + width, gen.height(feature.x + width));
+            let deck = (end - start) / planks as f32;
+            let length = deck.length();
+            let angle = deck.y.atan2(deck.x);
+            let half = Vec2::new(length / 2., 0.);
+            let chunk_offset = Vec2::new(chunk_x, 0.);
+
+            let mut previous = None;
+            for n in 0..planks {
+                let state = props
+                    .iter()
+                    .find(|p| p.feature == feature_index && p.index == n);
+                let broken = state.is_some_and(|s| s.broken);
+                let transform = match state {
+                    Some(state) => Transform::from_translation(state.position.extend(-0.5))
+                        .with_rotation(Quat::from_rotation_z(state.angle)),
+                    None => {
+                        Transform::from_translation((start + deck * (n as f32 + 0.5)).extend(-0.5))
+                            .with_rotation(Quat::from_rotation_z(angle))
+                    }
+                };
+                let joint = match previous {
+                    None => RevoluteJointBuilder::new()
+                        .local_anchor1(start - chunk_offset)
+                        .local_anchor2(-half),
+                    Some(_) => RevoluteJointBuilder::new()
+                        .local_anchor1(half)
+                        .local_anchor2(-half),
+                };
+                let mut plank = commands.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: PLANK_COLOR,
+                        custom_size: Some(Vec2::new(length - 2., 12.)),
+                        ..Default::default()
+                    },
+                    transform,
+                    ..Default::default()
+                });
+                plank
+                    .insert(RigidBody::Dynamic)
+                    .insert(Collider::cuboid(length / 2., 6.))
+                    .insert(AdditionalMassProperties::Mass(2.))
+                    .insert(CollisionGroups::new(SOLID_TERRAIN, LOOSE_ITEMS | PLAYER))
+                    .insert(BridgePlank {
+                        feature: feature_index,
+                        index: n,
+                        broken,
+                        strength,
+                    })
+                    .insert(ChunkMember(i));
+                if broken {
+                    previous = Some(plank.id());
+                    continue;
+                }
+                plank.insert(ImpulseJoint::new(previous.unwrap_or(anchor), joint.build()));
+                if n + 1 == planks {
+                    let joint = RevoluteJointBuilder::new()
+                        .local_anchor1(end - chunk_offset)
+                        .local_anchor2(half);
+                    plank.with_children(|b| {
+                        b.spawn_bundle(TransformBundle::default())
+                            .insert(ImpulseJoint::new(anchor, joint.build()));
+                    });
+                }
+                previous = Some(plank.id());
+            }
+        }
+        FeatureKind::Loop { radius } => {
+            let center_x = feature.x + feature.kind.length() / 2.;
+            let center = Vec2::new(center_x, gen.height(center_x) + radius);
+            // Open on the lower left so the car can drive in underneath the exit
+            let segments = 48;
+            let (from, to) = (-FRAC_PI_2, PI + 0.35);
+            let arc = (0..=segments)
+                .map(|s| Vec2::from_angle(from + (to - from) * s as f32 / segments as f32))
+                .collect::<Vec<_>>();
+
+            let mut mesh = Mesh::new(PrimitiveTopology::TriangleStrip);
+            let positions = arc
+                .iter()
+                .flat_map(|d| [(*d * radius).extend(0.), (*d * (radius + 16.)).extend(0.)])
+                .map(|v| v.to_array())
+                .collect::<Vec<_>>();
+            let count = positions.len();
+            mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; count]);
+            mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; count]);
+            mesh.insert_attribute(
+                Mesh::ATTRIBUTE_COLOR,
+                vec![LOOP_COLOR.as_linear_rgba_f32(); count],
+            );
+
+            commands
+                .spawn_bundle(MaterialMesh2dBundle {
+                    mesh: meshes.add(mesh).into(),
+                    material: material.clone(),
+                    transform: Transform::from_translation(center.extend(-0.5)),
+                    ..Default::default()
+                })
+                .insert(RigidBody::Fixed)
+                .insert(Collider::polyline(
+                    arc.iter().map(|d| *d * radius).collect(),
+                    None,
+                ))
+                .insert(CollisionGroups::new(SOLID_TERRAIN, LOOSE_ITEMS | PLAYER))
+                .insert(ChunkMember(i));
+        }
+        _ => {}
+    }
+}
+
+/// Planks give way once something pushes on them harder than they can hold.
+pub fn break_planks(
+    mut commands: Commands,
+    ctx: Res<RapierContext>,
+    mut planks: Query<(Entity, &mut BridgePlank, Option<&Children>)>,
+) {
+    planks.for_each_mut(|(entity, mut plank, children)| {
+        if plank.broken {
+            return;
+        }
+        let impulse: f32 = ctx
+            .contacts_with(entity)
+            .flat_map(|pair| {
+                pair.manifolds()
+                    .flat_map(|m| m.points().map(|p| p.impulse()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            })
+            .sum();
+        if impulse > plank.strength {
+            plank.broken = true;
+            commands.entity(entity).remove::<ImpulseJoint>();
+            children
+                .into_iter()
+                .flatten()
+                .for_each(|child| commands.entity(*child).despawn_recursive());
+        }
+    });
+}
@@ -1 +1,9 @@
+pub mod biome;
+pub mod cache;
 pub mod chunk;
+pub mod difficulty;
+pub mod features;
+pub mod parallax;
+pub mod seed;
+pub mod terrain;
+pub mod texture;
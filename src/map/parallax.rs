@@ -0,0 +1,270 @@
+use bevy::{
+    prelude::*, render::render_resource::PrimitiveTopology, sprite::MaterialMesh2dBundle,
+    transform::TransformSystem, utils::HashSet,
+};
+use itertools::{repeat_n, Itertools};
+use rand::Rng;
+
+use crate::{run::GameState, utils::iter::IteratorExt};
+
+use super::{
+    chunk::{Chunk, ChunkConfig, Chunkloader, TerrainMaterial},
+    seed::{streams, WorldSeed},
+    terrain::{LayeredNoise, NoiseKind, Octave, TerrainGenerator},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LayerKind {
+    Mountains,
+    Hills,
+    Clouds,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParallaxLayer {
+    pub kind: LayerKind,
+    /// How much of the camera movement the layer follows, 0 is glued to the world
+    /// and 1 is glued to the camera.
+    pub depth: f32,
+    pub z: f32,
+    pub color: Color,
+    /// Height of the layer relative to the camera.
+    pub base: f32,
+    pub octaves: Vec<Octave>,
+}
+
+#[derive(Debug)]
+pub struct ParallaxConfig {
+    pub layers: Vec<ParallaxLayer>,
+    pub probes: u32,
+    pub x_size: f32,
+    /// How far either side of the camera a layer is drawn, at least half the screen.
+    pub view_distance: f32,
+}
+
+impl Default for ParallaxConfig {
+    fn default() -> Self {
+        Self {
+            layers: vec![
+                ParallaxLayer {
+                    kind: LayerKind::Mountains,
+                    depth: 0.9,
+                    z: -30.,
+                    color: Color::rgb(0.55, 0.62, 0.75),
+                    base: -100.,
+                    octaves: vec![
+                        Octave {
+                            frequency: 0.002,
+                            amplitude: 250.,
+                        },
+                        Octave {
+                            frequency: 0.01,
+                            amplitude: 40.,
+                        },
+                    ],
+                },
+                ParallaxLayer {
+                    kind: LayerKind::Hills,
+                    depth: 0.7,
+                    z: -20.,
+                    color: Color::rgb(0.45, 0.65, 0.45),
+                    base: -250.,
+                    octaves: vec![
+                        Octave {
+                            frequency: 0.003,
+                            amplitude: 120.,
+                        },
+                        Octave {
+                            frequency: 0.012,
+                            amplitude: 15.,
+                        },
+                    ],
+                },
+                ParallaxLayer {
+                    kind: LayerKind::Clouds,
+                    depth: 0.95,
+                    z: -40.,
+                    color: Color::rgb(1., 1., 1.),
+                    base: 250.,
+                    octaves: vec![],
+                },
+            ],
+            probes: 33,
+            x_size: 1024.,
+            view_distance: 2048.,
+        }
+    }
+}
+
+/// Part of a background layer, positioned in layer space.
+#[derive(Debug, Component)]
+pub struct ParallaxSegment {
+    layer: usize,
+    index: i32,
+}
+
+/// Camera position scaled into the space of a layer.
+fn layer_x(layer: &ParallaxLayer, camera_x: f32) -> f32 {
+    camera_x * (1. - layer.depth)
+}
+
+/// Segments load and unload with the chunks, every chunk keeps the ones on screen
+/// while the camera is over it.
+#[allow(clippy::too_many_arguments)]
+fn generate_segments(
+    mut commands: Commands,
+    config: Res<ParallaxConfig>,
+    chunk_config: Res<ChunkConfig>,
+    seed: Res<WorldSeed>,
+    material: Res<TerrainMaterial>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    segments: Query<(Entity, &ParallaxSegment)>,
+    chunks: Query<&Transform, With<Chunk>>,
+) {
+    let half_size = chunk_config.x_size() / 2.;
+    let mut needed = HashSet::new();
+    chunks.for_each(|chunk| {
+        let x = chunk.translation.x;
+        config.layers.iter().enumerate().for_each(|(l, layer)| {
+            let min_x = layer_x(layer, x - half_size) - config.view_distance;
+            let max_x = layer_x(layer, x + half_size) + config.view_distance;
+            let min_i = (min_x / config.x_size).floor() as i32;
+            let max_i = (max_x / config.x_size).ceil() as i32;
+            needed.extend((min_i..=max_i).map(|i| (l, i)));
+        });
+    });
+
+    segments.for_each(|(entity, segment)| {
+        // Layers come from the seed, so a loaded save needs them rebuilt
+        if seed.is_changed() || !needed.remove(&(segment.layer, segment.index)) {
+            commands.entity(entity).despawn_recursive();
+        }
+    });
+    needed.into_iter().for_each(|(l, i)| {
+        spawn_segment(
+            &mut commands,
+            &config,
+            &seed,
+            material.plain(),
+            &mut meshes,
+            l,
+            i,
+        );
+    });
+}
+
+fn spawn_segment(
+    commands: &mut Commands,
+    config: &ParallaxConfig,
+    seed: &WorldSeed,
+    material: &Handle<ColorMaterial>,
+    meshes: &mut Assets<Mesh>,
+    l: usize,
+    i: i32,
+) {
+    let layer = &config.layers[l];
+    let stream = streams::PARALLAX ^ ((l as u64 + 1) << 16);
+    let x = i as f32 * config.x_size;
+    commands
+        .spawn_bundle(SpatialBundle::from_transform(Transform::from_xyz(
+            x, 0., layer.z,
+        )))
+        .insert(ParallaxSegment { layer: l, index: i })
+        .with_children(|b| match layer.kind {
+            LayerKind::Mountains | LayerKind::Hills => {
+                let noise = LayeredNoise {
+                    kind: NoiseKind::Perlin,
+                    seed: *seed,
+                    stream,
+                    octaves: layer.octaves.clone(),
+                };
+                b.spawn_bundle(MaterialMesh2dBundle {
+                    mesh: meshes.add(silhouette(config, &noise, layer, x)).into(),
+                    material: material.clone(),
+                    ..Default::default()
+                });
+            }
+            LayerKind::Clouds => {
+                let mut rng = seed.rng_at(stream, i as i64);
+                let mesh = meshes.add(Mesh::from(shape::Circle::new(1.)));
+                (0..rng.gen_range(0..3)).for_each(|_| {
+                    let center = Vec2::new(
+                        rng.gen_range(-0.5..0.5) * config.x_size,
+                        layer.base + rng.gen_range(-80. ..80.),
+                    );
+                    let [r0, r1, r2] = (0..3).map(|_| rng.gen_range(30. ..60.)).take_array();
+                    [(-r0, r0), (0., r1 * 1.3), (r2, r2)]
+                        .into_iter()
+                        .for_each(|(dx, r)| {
+                            b.spawn_bundle(MaterialMesh2dBundle {
+                                mesh: mesh.clone().into(),
+                                material: material.clone(),
+                                transform: Transform::from_translation(
+                                    (center + Vec2::new(dx, 0.)).extend(0.),
+                                )
+                                .with_scale(Vec3::splat(r)),
+                                ..Default::default()
+                            });
+                        });
+                });
+            }
+        });
+}
+
+fn silhouette(
+    config: &ParallaxConfig,
+    noise: &LayeredNoise,
+    layer: &ParallaxLayer,
+    x: f32,
+) -> Mesh {
+    let half_size = config.x_size / 2.;
+    let dx = config.x_size / (config.probes - 1) as f32;
+    let (top, bottom): (Vec<_>, Vec<_>) = (0..config.probes)
+        .map(|p| {
+            let lx = p as f32 * dx - half_size;
+            let y = layer.base + noise.height(x + lx).abs();
+            ([lx, y, 0.], [lx, layer.base - 2000., 0.])
+        })
+        .unzip();
+    let probes = top.len();
+    let mut mesh = Mesh::new(PrimitiveTopology::TriangleStrip);
+    let positions = top.into_iter().interleave(bottom).collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    let normals = repeat_n([0., 0., 1.], 2 * probes).collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, normals);
+    let uvs = repeat_n([0., 0.], 2 * probes).collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    let colors = repeat_n(layer.color.as_linear_rgba_f32(), 2 * probes).collect::<Vec<_>>();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+    mesh
+}
+
+/// Runs after the camera moved so the layers don't lag a frame behind.
+fn scroll(
+    config: Res<ParallaxConfig>,
+    mut segments: Query<(&ParallaxSegment, &mut Transform), Without<Chunkloader>>,
+    chunkloaders: Query<&Transform, With<Chunkloader>>,
+) {
+    let Some(camera) = chunkloaders.iter().next() else {
+        return;
+    };
+    segments.for_each_mut(|(segment, mut transform)| {
+        let layer = &config.layers[segment.layer];
+        let offset = camera.translation.truncate() * layer.depth;
+        transform.translation.x = segment.index as f32 * config.x_size + offset.x;
+        transform.translation.y = offset.y;
+    });
+}
+
+pub struct ParallaxPlugin;
+
+impl Plugin for ParallaxPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ParallaxConfig>()
+            .add_system_set(SystemSet::on_update(GameState::Playing).with_system(generate_segments))
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                scroll.before(TransformSystem::TransformPropagate),
+            );
+    }
+}
@@ -0,0 +1,82 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::utils::launch::launch_option;
+
+/// Rng used by everything that has to be reproducible from the world seed.
+/// `StdRng` is used over `SmallRng` because the latter differs between 32 and 64 bit targets.
+pub type WorldRng = StdRng;
+
+pub mod streams {
+    pub const TERRAIN_FREQUENCIES: u64 = 1;
+    pub const TERRAIN_PHASES: u64 = 2;
+    pub const TERRAIN_AMPLITUDES: u64 = 3;
+    pub const PACKAGES: u64 = 4;
+    /// Noise takes one stream per octave counting up from here, so the next ones start well above.
+    pub const TERRAIN_NOISE: u64 = 5;
+    pub const BIOMES: u64 = 100;
+    pub const FEATURES: u64 = 101;
+    pub const PARALLAX: u64 = 102;
+    pub const DEBRIS: u64 = 103;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WorldSeed(pub u64);
+
+impl Default for WorldSeed {
+    fn default() -> Self {
+        Self::from_launch_options().unwrap_or_else(|| Self(rand::random()))
+    }
+}
+
+impl WorldSeed {
+    /// Numeric seeds are used as is, anything else is hashed so tracks can be shared by name.
+    pub fn parse(value: &str) -> Self {
+        let value = value.trim();
+        match value.parse() {
+            Ok(seed) => Self(seed),
+            Err(_) => Self(fnv1a(value.as_bytes())),
+        }
+    }
+
+    /// Independent rng for one consumer of randomness.
+    pub fn rng(&self, stream: u64) -> WorldRng {
+        WorldRng::seed_from_u64(splitmix(self.0 ^ splitmix(stream)))
+    }
+
+    /// Independent rng for one indexed item of a stream, e.g. the n-th package.
+    pub fn rng_at(&self, stream: u64, index: i64) -> WorldRng {
+        WorldRng::seed_from_u64(self.hash_at(stream, index))
+    }
+
+    /// Cheap stateless alternative to `rng_at` for lattice based noise.
+    pub fn hash_at(&self, stream: u64, index: i64) -> u64 {
+        splitmix(self.0 ^ splitmix(stream) ^ splitmix(index as u64 ^ 0xA5A5_A5A5_A5A5_A5A5))
+    }
+
+    fn from_launch_options() -> Option<Self> {
+        launch_option("seed").map(|value| Self::parse(&value))
+    }
+}
+
+fn splitmix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^ (x >> 31)
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+pub fn show_seed(mut egui_context: ResMut<EguiContext>, seed: Res<WorldSeed>) {
+    egui::Area::new("seed")
+        .anchor(egui::Align2::RIGHT_TOP, [-8., 8.])
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!("Seed: {}", seed.0));
+        });
+}
@@ -0,0 +1,257 @@
+use std::fmt::Debug;
+
+use bevy::prelude::*;
+use itertools::izip;
+
+use crate::utils::launch::launch_option;
+
+use super::seed::WorldSeed;
+
+/// Heightfield source for chunks, height and slope are queried in world space.
+pub trait TerrainGenerator: Debug + Send + Sync {
+    fn height(&self, x: f32) -> f32;
+    fn slope(&self, x: f32) -> f32;
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CosineSum {
+    pub frequencies: Vec<f32>,
+    pub phases: Vec<f32>,
+    pub amplitudes: Vec<f32>,
+}
+
+impl TerrainGenerator for CosineSum {
+    fn height(&self, x: f32) -> f32 {
+        izip!(&self.frequencies, &self.phases, &self.amplitudes)
+            .map(|(f, p, a)| (x * f + p).cos() * a)
+            .sum()
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        izip!(&self.frequencies, &self.phases, &self.amplitudes)
+            .map(|(f, p, a)| -(x * f + p).sin() * f * a)
+            .sum()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoiseKind {
+    Value,
+    Perlin,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Octave {
+    pub frequency: f32,
+    pub amplitude: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LayeredNoise {
+    pub kind: NoiseKind,
+    pub seed: WorldSeed,
+    pub stream: u64,
+    pub octaves: Vec<Octave>,
+}
+
+impl LayeredNoise {
+    /// Lattice value in [-1, 1], also used as the gradient for perlin noise.
+    fn lattice(&self, octave: usize, i: i64) -> f32 {
+        let hash = self.seed.hash_at(self.stream + octave as u64, i);
+        (hash >> 40) as f32 / (1u64 << 23) as f32 - 1.
+    }
+
+    /// Returns noise value and its derivative with respect to `t`.
+    fn sample(&self, octave: usize, t: f32) -> (f32, f32) {
+        let i = t.floor();
+        let f = t - i;
+        let i = i as i64;
+        let s = f * f * f * (f * (f * 6. - 15.) + 10.);
+        let ds = 30. * f * f * (f - 1.) * (f - 1.);
+        let (a, b) = (self.lattice(octave, i), self.lattice(octave, i + 1));
+        match self.kind {
+            NoiseKind::Value => (a + s * (b - a), ds * (b - a)),
+            NoiseKind::Perlin => {
+                let (va, vb) = (a * f, b * (f - 1.));
+                (va + s * (vb - va), a + s * (b - a) + ds * (vb - va))
+            }
+        }
+    }
+}
+
+impl TerrainGenerator for LayeredNoise {
+    fn height(&self, x: f32) -> f32 {
+        self.octaves
+            .iter()
+            .enumerate()
+            .map(|(i, o)| self.sample(i, x * o.frequency).0 * o.amplitude)
+            .sum()
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        self.octaves
+            .iter()
+            .enumerate()
+            .map(|(i, o)| self.sample(i, x * o.frequency).1 * o.frequency * o.amplitude)
+            .sum()
+    }
+}
+
+/// Hand authored profile, points have to be sorted by `x`.
+/// Outside of the points the height of the closest end is kept.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub points: Vec<Vec2>,
+}
+
+impl Profile {
+    fn segment(&self, x: f32) -> Option<(Vec2, Vec2)> {
+        let i = self.points.partition_point(|p| p.x <= x);
+        if i == 0 || i == self.points.len() {
+            None
+        } else {
+            Some((self.points[i - 1], self.points[i]))
+        }
+    }
+}
+
+impl TerrainGenerator for Profile {
+    fn height(&self, x: f32) -> f32 {
+        match self.segment(x) {
+            Some((a, b)) => a.y + (b.y - a.y) * (x - a.x) / (b.x - a.x),
+            None => match (self.points.first(), self.points.last()) {
+                (Some(first), _) if x < first.x => first.y,
+                (_, Some(last)) => last.y,
+                _ => 0.,
+            },
+        }
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        match self.segment(x) {
+            Some((a, b)) => (b.y - a.y) / (b.x - a.x),
+            None => 0.,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Add(pub Box<dyn TerrainGenerator>, pub Box<dyn TerrainGenerator>);
+
+impl TerrainGenerator for Add {
+    fn height(&self, x: f32) -> f32 {
+        self.0.height(x) + self.1.height(x)
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        self.0.slope(x) + self.1.slope(x)
+    }
+}
+
+#[derive(Debug)]
+pub struct Multiply(pub Box<dyn TerrainGenerator>, pub Box<dyn TerrainGenerator>);
+
+impl TerrainGenerator for Multiply {
+    fn height(&self, x: f32) -> f32 {
+        self.0.height(x) * self.1.height(x)
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        self.0.slope(x) * self.1.height(x) + self.0.height(x) * self.1.slope(x)
+    }
+}
+
+/// Constant height, mostly useful as a factor or offset in composites.
+#[derive(Debug, Clone, Copy)]
+pub struct Constant(pub f32);
+
+impl TerrainGenerator for Constant {
+    fn height(&self, _: f32) -> f32 {
+        self.0
+    }
+
+    fn slope(&self, _: f32) -> f32 {
+        0.
+    }
+}
+
+#[derive(Debug)]
+pub struct Clamp {
+    pub inner: Box<dyn TerrainGenerator>,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl TerrainGenerator for Clamp {
+    fn height(&self, x: f32) -> f32 {
+        self.inner.height(x).clamp(self.min, self.max)
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let height = self.inner.height(x);
+        if height < self.min || height > self.max {
+            0.
+        } else {
+            self.inner.slope(x)
+        }
+    }
+}
+
+/// Smoothly transitions from `from` to `to` between `start` and `end`.
+#[derive(Debug)]
+pub struct Blend {
+    pub from: Box<dyn TerrainGenerator>,
+    pub to: Box<dyn TerrainGenerator>,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Blend {
+    fn weight(&self, x: f32) -> (f32, f32) {
+        let length = self.end - self.start;
+        let t = ((x - self.start) / length).clamp(0., 1.);
+        let dt = if t > 0. && t < 1. { 1. / length } else { 0. };
+        (t * t * (3. - 2. * t), 6. * t * (1. - t) * dt)
+    }
+}
+
+impl TerrainGenerator for Blend {
+    fn height(&self, x: f32) -> f32 {
+        let (w, _) = self.weight(x);
+        self.from.height(x) * (1. - w) + self.to.height(x) * w
+    }
+
+    fn slope(&self, x: f32) -> f32 {
+        let (w, dw) = self.weight(x);
+        let (from, to) = (self.from.height(x), self.to.height(x));
+        self.from.slope(x) * (1. - w) + self.to.slope(x) * w + (to - from) * dw
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TerrainKind {
+    Hills,
+    Noise,
+    Dunes,
+    Mixed,
+}
+
+impl Default for TerrainKind {
+    fn default() -> Self {
+        launch_option("terrain")
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or(Self::Hills)
+    }
+}
+
+impl TerrainKind {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "hills" => Some(Self::Hills),
+            "noise" => Some(Self::Noise),
+            "dunes" => Some(Self::Dunes),
+            "mixed" => Some(Self::Mixed),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use bevy::{
+    prelude::*,
+    render::{
+        render_resource::{
+            AddressMode, Extent3d, FilterMode, SamplerDescriptor, TextureDimension, TextureFormat,
+        },
+        texture::ImageSampler,
+    },
+};
+
+use super::seed::WorldSeed;
+
+/// Greyscale detail for a terrain layer, the colour comes from the biome vertex colours.
+#[derive(Debug, Clone, Copy)]
+pub struct TexturePattern {
+    /// Noise cells across the tile, horizontally and vertically.
+    pub cells: (u32, u32),
+    pub octaves: u32,
+    pub contrast: f32,
+    /// Strength of the horizontal bands used for ripples and strata.
+    pub bands: f32,
+    /// How much darker the bottom of the tile gets.
+    pub shade: f32,
+    pub seed: u64,
+}
+
+impl TexturePattern {
+    fn lattice(&self, period: (u32, u32), octave: u32, x: u32, y: u32) -> f32 {
+        let (x, y) = (x % period.0, y % period.1);
+        let hash = WorldSeed(self.seed).hash_at(octave as u64, (y * period.0 + x) as i64);
+        (hash >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    /// Value noise that wraps around at the tile edges.
+    fn noise(&self, octave: u32, u: f32, v: f32) -> f32 {
+        let period = (self.cells.0 << octave, self.cells.1 << octave);
+        let (x, y) = (u * period.0 as f32, v * period.1 as f32);
+        let (ix, iy) = (x.floor(), y.floor());
+        let (fx, fy) = (x - ix, y - iy);
+        let (sx, sy) = (fx * fx * (3. - 2. * fx), fy * fy * (3. - 2. * fy));
+        let (ix, iy) = (ix as u32, iy as u32);
+        let a = self.lattice(period, octave, ix, iy);
+        let b = self.lattice(period, octave, ix + 1, iy);
+        let c = self.lattice(period, octave, ix, iy + 1);
+        let d = self.lattice(period, octave, ix + 1, iy + 1);
+        let top = a + (b - a) * sx;
+        let bottom = c + (d - c) * sx;
+        top + (bottom - top) * sy
+    }
+
+    fn luminance(&self, u: f32, v: f32) -> f32 {
+        let (sum, weight) = (0..self.octaves).fold((0., 0.), |(sum, weight), octave| {
+            let w = 0.5f32.powi(octave as i32);
+            (sum + self.noise(octave, u, v) * w, weight + w)
+        });
+        let noise = sum / weight - 0.5;
+        let bands = (v * self.cells.1 as f32 * std::f32::consts::TAU + noise * 4.).sin();
+        1. - self.contrast * 0.5 + self.contrast * noise + self.bands * bands * 0.5 - self.shade * v
+    }
+
+    pub fn image(&self, width: u32, height: u32) -> Image {
+        let data = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                let (u, v) = (x as f32 / width as f32, y as f32 / height as f32);
+                let l = (self.luminance(u, v).clamp(0., 1.) * 255.) as u8;
+                [l, l, l, 255]
+            })
+            .collect();
+        let mut image = Image::new(
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        image.sampler_descriptor = ImageSampler::Descriptor(SamplerDescriptor {
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+        image
+    }
+}
+
+/// Shared by every biome, only tinted differently.
+pub const ROCK_TEXTURE: TexturePattern = TexturePattern {
+    cells: (6, 6),
+    octaves: 4,
+    contrast: 0.6,
+    bands: 0.1,
+    shade: 0.,
+    seed: 31,
+};
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::RapierContext;
+
+use crate::{
+    controls::{Action, Actions},
+    packages::{
+        damage::Durability,
+        delivery::Money,
+        presets::{Cargo, PresetList, Presets},
+    },
+};
+
+use super::{
+    graph::JointGraph,
+    mode::AttachConfig,
+    tool::{check_package, detach, pick_up, Nailgun},
+};
+
+/// The last few nails, most recent at the back.
+#[derive(Debug)]
+pub struct NailHistory {
+    nails: VecDeque<(Entity, u32)>,
+    pub limit: usize,
+}
+
+impl Default for NailHistory {
+    fn default() -> Self {
+        Self {
+            nails: VecDeque::new(),
+            limit: 10,
+        }
+    }
+}
+
+impl NailHistory {
+    /// Remembers the nail and what it cost, forgetting the oldest past the limit.
+    pub fn push(&mut self, entity: Entity, cost: u32) {
+        self.nails.push_back((entity, cost));
+        while self.nails.len() > self.limit {
+            self.nails.pop_front();
+        }
+    }
+}
+
+pub fn reset_history(mut history: ResMut<NailHistory>) {
+    history.nails.clear();
+}
+
+/// Takes the cargo under the tool off and holds it so it can be nailed somewhere else.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+pub fn detach_cargo(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    ctx: Res<RapierContext>,
+    config: Res<AttachConfig>,
+    mut money: ResMut<Money>,
+    graph: Res<JointGraph>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
+    asset_server: Res<AssetServer>,
+    mut tool: Query<
+        (
+            &mut Nailgun,
+            &mut Transform,
+            &mut Handle<Image>,
+            &mut Sprite,
+        ),
+        Without<Cargo>,
+    >,
+    cargo: Query<
+        (
+            &Cargo,
+            &Transform,
+            &Handle<Image>,
+            &Sprite,
+            Option<&Durability>,
+        ),
+        Without<Nailgun>,
+    >,
+) {
+    let Ok((mut nailgun, mut tool_transform, mut tool_image, mut tool_sprite)) =
+        tool.get_single_mut()
+    else {
+        return;
+    };
+    if nailgun.is_holding() || !actions.just_pressed(Action::Detach) {
+        return;
+    }
+    let position = tool_transform.translation.truncate();
+    let Some(entity) = check_package(&ctx, position, &cargo) else {
+        return;
+    };
+    // Stacks come apart from the top
+    if !graph.children(entity).is_empty() || money.0 < config.detach_cost {
+        return;
+    }
+    let Ok((cargo, transform, image, sprite, durability)) = cargo.get(entity) else {
+        return;
+    };
+    let Some(preset) = lists.get(&presets.0).and_then(|l| l.by_name(&cargo.name)) else {
+        return;
+    };
+
+    money.0 -= config.detach_cost;
+    money.save();
+    detach(
+        &mut commands.entity(entity),
+        preset,
+        durability,
+        &asset_server,
+    );
+    pick_up(
+        &mut nailgun,
+        &mut tool_image,
+        &mut tool_transform,
+        &mut tool_sprite,
+        entity,
+        transform,
+        image,
+        sprite,
+        preset.is_point,
+    );
+}
+
+/// Turns the most recently nailed cargo that is still around back into a loose package and refunds it.
+#[allow(clippy::too_many_arguments)]
+pub fn undo_nail(
+    mut commands: Commands,
+    actions: Res<Actions>,
+    mut history: ResMut<NailHistory>,
+    mut money: ResMut<Money>,
+    graph: Res<JointGraph>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
+    asset_server: Res<AssetServer>,
+    cargo: Query<(&Cargo, Option<&Durability>)>,
+) {
+    if !actions.just_pressed(Action::Undo) {
+        return;
+    }
+    let Some(list) = lists.get(&presets.0) else {
+        return;
+    };
+    // Delivered or lost cargo and cargo that was taken off since can't be undone
+    while let Some((entity, cost)) = history.nails.pop_back() {
+        let Ok((cargo, durability)) = cargo.get(entity) else {
+            continue;
+        };
+        let Some(preset) = list.by_name(&cargo.name) else {
+            continue;
+        };
+        if !graph.children(entity).is_empty() {
+            // Something was nailed onto it and has since dropped out of the history
+            history.nails.push_back((entity, cost));
+            return;
+        }
+        money.0 += cost;
+        money.save();
+        detach(
+            &mut commands.entity(entity),
+            preset,
+            durability,
+            &asset_server,
+        );
+        return;
+    }
+}
@@ -0,0 +1,87 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use bevy_rapier2d::prelude::ImpulseJoint;
+
+use crate::{packages::presets::Cargo, player::car::Chassis};
+
+use super::{mode::AttachMode, tool::Nailed};
+
+/// What every piece of cargo is nailed onto, rebuilt every frame from the joints.
+/// Cargo can only be nailed onto something already in the graph, so it is a tree per car.
+#[derive(Debug, Default)]
+pub struct JointGraph {
+    parents: HashMap<Entity, (Entity, AttachMode)>,
+    children: HashMap<Entity, Vec<Entity>>,
+}
+
+impl JointGraph {
+    pub fn mode(&self, entity: Entity) -> Option<AttachMode> {
+        self.parents.get(&entity).map(|(_, mode)| *mode)
+    }
+
+    pub fn children(&self, entity: Entity) -> &[Entity] {
+        self.children.get(&entity).map_or(&[], Vec::as_slice)
+    }
+
+    /// Everything nailed onto `entity` directly or through other cargo, parents before children.
+    pub fn descendants(&self, entity: Entity) -> Vec<Entity> {
+        let mut found = self.children(entity).to_vec();
+        let mut i = 0;
+        while let Some(next) = found.get(i) {
+            found.extend_from_slice(self.children(*next));
+            i += 1;
+        }
+        found
+    }
+}
+
+pub fn update_graph(
+    mut graph: ResMut<JointGraph>,
+    nailed: Query<(Entity, &Nailed, &ImpulseJoint)>,
+) {
+    graph.parents.clear();
+    graph.children.clear();
+    nailed.for_each(|(entity, nailed, joint)| {
+        graph.parents.insert(entity, (joint.parent, nailed.mode));
+        graph.children.entry(joint.parent).or_default().push(entity);
+    });
+}
+
+/// `F3` toggles a window showing what is nailed onto what.
+pub fn show_graph(
+    mut egui_context: ResMut<EguiContext>,
+    keys: Res<Input<KeyCode>>,
+    mut open: Local<bool>,
+    graph: Res<JointGraph>,
+    chassis: Query<Entity, With<Chassis>>,
+    cargo: Query<&Cargo>,
+) {
+    if keys.just_pressed(KeyCode::F3) {
+        *open = !*open;
+    }
+    if !*open {
+        return;
+    }
+    egui::Window::new("Cargo joints")
+        .open(&mut open)
+        .show(egui_context.ctx_mut(), |ui| {
+            chassis.for_each(|chassis| {
+                ui.label(format!(
+                    "Chassis, {} pieces of cargo",
+                    graph.descendants(chassis).len()
+                ));
+                show_children(ui, &graph, &cargo, chassis);
+            });
+        });
+}
+
+fn show_children(ui: &mut egui::Ui, graph: &JointGraph, cargo: &Query<&Cargo>, parent: Entity) {
+    ui.indent(parent, |ui| {
+        graph.children(parent).iter().for_each(|child| {
+            let name = cargo.get(*child).map_or("?", |c| c.name.as_str());
+            let mode = graph.mode(*child).map_or("?", |m| m.name());
+            ui.label(format!("{name} ({mode})"));
+            show_children(ui, graph, cargo, *child);
+        });
+    });
+}
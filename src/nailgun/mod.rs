@@ -1,7 +1,19 @@
 use bevy::prelude::*;
 
-use self::tool::{follow_cursor, init, nail, update_state, ZSequencer};
+use crate::run::GameState;
 
+use self::{
+    detach::{detach_cargo, reset_history, undo_nail, NailHistory},
+    graph::{show_graph, update_graph, JointGraph},
+    mode::{cycle_mode, show_mode, tighten_ropes, AttachConfig},
+    rotate::{flip_cargo, rotate_item, RotateConfig},
+    tool::{follow_cursor, init, nail, update_state, ZSequencer},
+};
+
+pub mod detach;
+pub mod graph;
+pub mod mode;
+pub mod rotate;
 pub mod tool;
 
 pub struct ToolPlugin;
@@ -9,8 +21,29 @@ pub struct ToolPlugin;
 impl Plugin for ToolPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
         app.init_resource::<ZSequencer>()
-            .add_startup_system(init)
-            .add_system(follow_cursor)
-            .add_system(update_state.chain(nail).after(follow_cursor));
+            .init_resource::<AttachConfig>()
+            .init_resource::<JointGraph>()
+            .init_resource::<NailHistory>()
+            .init_resource::<RotateConfig>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(init)
+                    .with_system(reset_history),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(follow_cursor)
+                    .with_system(cycle_mode.before(update_state))
+                    .with_system(rotate_item.before("nail"))
+                    .with_system(flip_cargo)
+                    .with_system(show_mode)
+                    .with_system(tighten_ropes)
+                    .with_system(update_graph)
+                    .with_system(show_graph)
+                    .with_system(update_state.chain(nail).after(follow_cursor).label("nail"))
+                    // Detached cargo only becomes a package at the end of the frame
+                    .with_system(detach_cargo.after("nail"))
+                    .with_system(undo_nail),
+            );
     }
 }
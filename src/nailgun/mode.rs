@@ -0,0 +1,210 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use bevy_rapier2d::{
+    prelude::{GenericJoint, ImpulseJoint, Velocity},
+    rapier::prelude::{JointAxesMask, JointAxis},
+};
+
+use crate::controls::{Action, Actions};
+
+use super::{
+    rotate::RotateConfig,
+    tool::{Nailed, Nailgun},
+};
+
+/// How the nailgun joins the held item to what it is nailed onto.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum AttachMode {
+    #[default]
+    Weld,
+    /// Free to spin around the nail, point items are always hinged.
+    Hinge,
+    /// Hangs loosely within reach of the nail.
+    Rope,
+    /// Pulled back to the nail without turning.
+    Spring,
+    /// Slides along the length of the body it is nailed onto.
+    Slider,
+}
+
+impl AttachMode {
+    const ALL: [AttachMode; 5] = [
+        AttachMode::Weld,
+        AttachMode::Hinge,
+        AttachMode::Rope,
+        AttachMode::Spring,
+        AttachMode::Slider,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AttachMode::Weld => "Weld",
+            AttachMode::Hinge => "Hinge",
+            AttachMode::Rope => "Rope",
+            AttachMode::Spring => "Spring",
+            AttachMode::Slider => "Slider",
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        let i = Self::ALL.iter().position(|m| m == self).unwrap_or(0);
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    /// The mode an item is actually nailed with.
+    pub fn for_item(&self, is_point: bool) -> Self {
+        if is_point {
+            AttachMode::Hinge
+        } else {
+            *self
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AttachConfig {
+    /// How far a roped item can get from the nail.
+    pub rope_length: f32,
+    pub spring_stiffness: f32,
+    pub spring_damping: f32,
+    /// How far a slider moves either way from where it was nailed.
+    pub slider_travel: f32,
+    /// Charged for every nail.
+    pub costs: HashMap<AttachMode, u32>,
+    /// Charged for taking cargo off again, undoing a nail is free.
+    pub detach_cost: u32,
+}
+
+impl Default for AttachConfig {
+    fn default() -> Self {
+        Self {
+            rope_length: 60.,
+            spring_stiffness: 300.,
+            spring_damping: 20.,
+            slider_travel: 50.,
+            costs: HashMap::from_iter([
+                (AttachMode::Weld, 0),
+                (AttachMode::Hinge, 1),
+                (AttachMode::Rope, 1),
+                (AttachMode::Spring, 2),
+                (AttachMode::Slider, 2),
+            ]),
+            detach_cost: 1,
+        }
+    }
+}
+
+impl AttachConfig {
+    pub fn cost(&self, mode: AttachMode) -> u32 {
+        self.costs.get(&mode).copied().unwrap_or(0)
+    }
+
+    /// Joint between a parent at angle `b2` and the item at angle `b1`, both anchored at the nail.
+    /// `a1` and `a2` point from the nail to the item and to the parent.
+    pub fn joint(&self, mode: AttachMode, a1: Vec2, a2: Vec2, b1: f32, b2: f32) -> GenericJoint {
+        let locked = match mode {
+            AttachMode::Weld => JointAxesMask::LOCKED_FIXED_AXES,
+            AttachMode::Hinge => JointAxesMask::LOCKED_REVOLUTE_AXES,
+            AttachMode::Rope => JointAxesMask::empty(),
+            AttachMode::Spring => JointAxesMask::ANG_X,
+            AttachMode::Slider => JointAxesMask::LOCKED_PRISMATIC_AXES,
+        };
+        let mut joint = GenericJoint::new(locked);
+
+        let a1 = a1.rotate(Vec2::from_angle(-b1));
+        let a2 = a2.rotate(Vec2::from_angle(-b2));
+
+        joint.set_local_anchor1(-a2);
+        joint.set_local_anchor2(-a1);
+        // Both frames line up with the parent, so the slider runs along it
+        joint.set_local_basis1(0.);
+        joint.set_local_basis2(b2 - b1);
+
+        match mode {
+            AttachMode::Weld | AttachMode::Hinge => {}
+            // Rapier has no distance joint yet, `tighten_ropes` rounds off this square
+            AttachMode::Rope => {
+                let reach = [-self.rope_length, self.rope_length];
+                joint.set_limits(JointAxis::X, reach);
+                joint.set_limits(JointAxis::Y, reach);
+            }
+            AttachMode::Spring => {
+                [JointAxis::X, JointAxis::Y].into_iter().for_each(|axis| {
+                    joint.set_motor_position(axis, 0., self.spring_stiffness, self.spring_damping);
+                });
+            }
+            AttachMode::Slider => {
+                joint.set_limits(JointAxis::X, [-self.slider_travel, self.slider_travel]);
+            }
+        }
+        joint
+    }
+}
+
+pub fn cycle_mode(actions: Res<Actions>, mut tool: Query<&mut Nailgun>) {
+    if actions.just_pressed(Action::CycleMode) {
+        tool.for_each_mut(|mut tool| tool.mode = tool.mode.next());
+    }
+}
+
+/// Pulls roped items that swing past `rope_length` back onto the rope and stops them moving further out.
+pub fn tighten_ropes(
+    config: Res<AttachConfig>,
+    ropes: Query<(Entity, &Nailed, &ImpulseJoint)>,
+    mut bodies: Query<(&mut Transform, Option<&mut Velocity>)>,
+) {
+    ropes.for_each(|(entity, nailed, joint)| {
+        if nailed.mode != AttachMode::Rope {
+            return;
+        }
+        let Ok((parent, parent_velocity)) = bodies.get(joint.parent) else {
+            return;
+        };
+        let nail = parent
+            .mul_vec3(joint.data.local_anchor1().extend(0.))
+            .truncate();
+        let parent_velocity = parent_velocity.copied().unwrap_or_default();
+        let Ok((mut transform, velocity)) = bodies.get_mut(entity) else {
+            return;
+        };
+        let offset = transform
+            .mul_vec3(joint.data.local_anchor2().extend(0.))
+            .truncate()
+            - nail;
+        let length = offset.length();
+        if length <= config.rope_length {
+            return;
+        }
+        let direction = offset / length;
+        transform.translation -= (direction * (length - config.rope_length)).extend(0.);
+        if let Some(mut velocity) = velocity {
+            let outward = (velocity.linvel - parent_velocity.linvel).dot(direction);
+            if outward > 0. {
+                velocity.linvel -= direction * outward;
+            }
+        }
+    });
+}
+
+pub fn show_mode(
+    mut egui_context: ResMut<EguiContext>,
+    config: Res<AttachConfig>,
+    rotate: Res<RotateConfig>,
+    tool: Query<&Nailgun>,
+) {
+    let Some(tool) = tool.iter().next() else {
+        return;
+    };
+    let mode = tool.held_mode().unwrap_or(tool.mode);
+    egui::Area::new("attach_mode")
+        .anchor(egui::Align2::LEFT_BOTTOM, [8., -8.])
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(match config.cost(mode) {
+                0 => format!("Nail: {}", mode.name()),
+                cost => format!("Nail: {} (${cost})", mode.name()),
+            });
+            if rotate.snap {
+                ui.label(format!("Snap: {:.0}°", rotate.snap_angle.to_degrees()));
+            }
+        });
+}
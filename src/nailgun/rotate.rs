@@ -0,0 +1,103 @@
+use std::f32::consts::PI;
+
+use bevy::{
+    input::mouse::{MouseScrollUnit, MouseWheel},
+    prelude::*,
+};
+use bevy_inspector_egui::bevy_egui::EguiContext;
+
+use crate::{
+    controls::{Action, Actions},
+    packages::presets::Cargo,
+};
+
+use super::tool::{pose_tool, Nailgun};
+
+/// Touchpads scroll in pixels, this many make up a line of the mouse wheel.
+const PIXELS_PER_LINE: f32 = 50.;
+
+#[derive(Debug)]
+pub struct RotateConfig {
+    /// Radians per second while a rotate action is held.
+    pub speed: f32,
+    /// Radians per line of the mouse wheel.
+    pub wheel_step: f32,
+    pub snap: bool,
+    /// Snapped items turn by this much per press or line of the mouse wheel.
+    pub snap_angle: f32,
+}
+
+impl Default for RotateConfig {
+    fn default() -> Self {
+        Self {
+            speed: PI,
+            wheel_step: PI / 36.,
+            snap: false,
+            snap_angle: PI / 12.,
+        }
+    }
+}
+
+/// Turns and mirrors the held item, `update_state` then checks whether it still fits.
+pub fn rotate_item(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    actions: Res<Actions>,
+    mut config: ResMut<RotateConfig>,
+    mut wheel: EventReader<MouseWheel>,
+    mut scrolled: Local<f32>,
+    mut tool: Query<(&mut Nailgun, &mut Transform, &mut Sprite)>,
+) {
+    if actions.just_pressed(Action::Snap) {
+        config.snap = !config.snap;
+    }
+    // Scrolling a window doesn't turn the item
+    let over_window = egui_context.ctx_mut().is_pointer_over_area();
+    let lines = wheel
+        .iter()
+        .filter(|_| !over_window)
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum::<f32>();
+
+    let Ok((mut nailgun, mut transform, mut sprite)) = tool.get_single_mut() else {
+        return;
+    };
+    let Some(item) = nailgun.item_mut() else {
+        *scrolled = 0.;
+        return;
+    };
+
+    let flip = actions.just_pressed(Action::Flip);
+    if flip {
+        item.flip();
+    }
+    let angle = if config.snap {
+        let presses = |action| if actions.just_pressed(action) { 1. } else { 0. };
+        // Touchpads scroll a fraction of a line at a time, a step is taken per whole line
+        *scrolled += lines;
+        let whole = scrolled.trunc();
+        *scrolled -= whole;
+        let steps = presses(Action::RotateLeft) - presses(Action::RotateRight) + whole;
+        ((item.angle() / config.snap_angle).round() + steps) * config.snap_angle
+    } else {
+        let held = actions.axis(Action::RotateLeft, Action::RotateRight);
+        item.angle() + held * config.speed * time.delta_seconds() + lines * config.wheel_step
+    };
+    if flip || angle != item.angle() {
+        item.turn_to(angle);
+        pose_tool(&mut transform, &mut sprite, item);
+    }
+}
+
+/// Mirrors the sprites of flipped cargo, respawning and reloading presets put back plain ones.
+#[allow(clippy::type_complexity)]
+pub fn flip_cargo(mut cargo: Query<(&Cargo, &mut Sprite), Or<(Changed<Cargo>, Changed<Sprite>)>>) {
+    cargo.for_each_mut(|(cargo, mut sprite)| {
+        if sprite.flip_x != cargo.flipped {
+            sprite.flip_x = cargo.flipped;
+        }
+    });
+}
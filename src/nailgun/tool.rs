@@ -1,24 +1,80 @@
-use bevy::{ecs::query::WorldQuery, prelude::*, render::camera::CameraProjection, sprite::Anchor};
-use bevy_rapier2d::{prelude::*, rapier::prelude::JointAxesMask};
+use bevy::{
+    ecs::{query::WorldQuery, system::EntityCommands},
+    prelude::*,
+    render::camera::CameraProjection,
+    sprite::Anchor,
+};
+use bevy_rapier2d::{prelude::*, rapier::prelude::JointAxis};
 
 use crate::{
     collision_groups::*,
+    controls::{cursor::VirtualCursor, Action, Actions},
     map::chunk::Chunkloader,
-    packages::presets::Package,
-    player::car::Chassis,
+    packages::{
+        damage::Durability,
+        delivery::Money,
+        presets::{Cargo, Package, Preset},
+    },
+    player::car::Wheel,
     utils::{quat::rot_z, secondary_handle::SecondaryHandle},
 };
 
+use super::{
+    detach::NailHistory,
+    mode::{AttachConfig, AttachMode},
+};
+
 #[derive(Debug)]
 pub struct SelectedItem {
     entity: Entity,
     linear_offset: Vec2,
     angular_offset: f32,
+    is_point: bool,
+    flipped: bool,
+}
+
+impl SelectedItem {
+    pub fn angle(&self) -> f32 {
+        self.angular_offset
+    }
+
+    /// Turns the item to `angle` around the point it is held at.
+    pub fn turn_to(&mut self, angle: f32) {
+        self.linear_offset =
+            Vec2::from_angle(angle - self.angular_offset).rotate(self.linear_offset);
+        self.angular_offset = angle;
+    }
+
+    /// Mirrors the item left to right around the point it is held at.
+    pub fn flip(&mut self) {
+        let held = Vec2::from_angle(-self.angular_offset).rotate(self.linear_offset);
+        self.linear_offset =
+            Vec2::from_angle(self.angular_offset).rotate(Vec2::new(-held.x, held.y));
+        self.flipped = !self.flipped;
+    }
 }
 
 #[derive(Debug, Default, Component)]
 pub struct Nailgun {
     item: Option<SelectedItem>,
+    pub mode: AttachMode,
+}
+
+impl Nailgun {
+    /// The mode the held item would be nailed with.
+    pub fn held_mode(&self) -> Option<AttachMode> {
+        self.item
+            .as_ref()
+            .map(|item| self.mode.for_item(item.is_point))
+    }
+
+    pub fn is_holding(&self) -> bool {
+        self.item.is_some()
+    }
+
+    pub fn item_mut(&mut self) -> Option<&mut SelectedItem> {
+        self.item.as_mut()
+    }
 }
 
 pub fn init(mut commands: Commands, asset_server: Res<AssetServer>) {
@@ -39,13 +95,14 @@ pub fn init(mut commands: Commands, asset_server: Res<AssetServer>) {
 
 pub fn follow_cursor(
     windows: Res<Windows>,
+    virtual_cursor: Res<VirtualCursor>,
     mut tool: Query<&mut Transform, (With<Nailgun>, Without<Chunkloader>)>,
     camera: Query<(&Transform, &OrthographicProjection), With<Chunkloader>>,
 ) {
     let window = windows.primary();
-    if let Some(cursor) = window.cursor_position() {
+    if let Some(cursor) = virtual_cursor.position {
         let normalized_screen_position =
-            cursor / Vec2::new(window.width() as f32, window.height() as f32) * 2. - 1.;
+            cursor / Vec2::new(window.width(), window.height()) * 2. - 1.;
         let (camera_transform, camera_projection) = camera.single();
         let screen_to_world =
             camera_transform.compute_matrix() * camera_projection.get_projection_matrix().inverse();
@@ -69,7 +126,7 @@ impl ZSequencer {
     }
 }
 
-#[allow(clippy::type_complexity)]
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
 pub fn update_state(
     mut tool: Query<
         (
@@ -83,11 +140,12 @@ pub fn update_state(
     >,
     packages: Query<(&Transform, &Handle<Image>, &Sprite, &Package)>,
     anchorable: Query<(), With<Anchorable>>,
-    mouse: Res<Input<MouseButton>>,
-    keyboard: Res<Input<KeyCode>>,
+    actions: Res<Actions>,
     ctx: Res<RapierContext>,
     colliders: Query<&Collider>,
-) -> Option<Vec2> {
+    config: Res<AttachConfig>,
+    money: Res<Money>,
+) -> Option<(Vec2, Entity)> {
     let mut tool = tool.single_mut();
     let position = tool.1.translation.truncate();
 
@@ -106,54 +164,44 @@ pub fn update_state(
         let can_place = check_placeable(&ctx, &anchorable, collider, pos, rot);
 
         let is_point = packages.get(item.entity).unwrap().3.is_point;
-        let is_anchor = match is_point {
+        // Placeable items overlap a single anchorable at most, that's what they get nailed onto
+        let anchor = match is_point {
             true => check_anchor_point(&ctx, &anchorable, position),
             false => check_anchor_shape(&ctx, &anchorable, collider, pos, rot),
         };
 
-        if is_anchor && can_place {
-            if mouse.just_pressed(MouseButton::Left) || keyboard.just_pressed(KeyCode::Q) {
+        let affordable = config.cost(tool.0.mode.for_item(is_point)) <= money.0;
+
+        if let Some(anchor) = anchor.filter(|_| can_place && affordable) {
+            if actions.just_pressed(Action::Nail) {
                 unset_tool(&mut tool.2, &(tool.4 .0), &mut tool.1, &mut tool.3);
-                return Some(position);
+                return Some((position, anchor));
             } else if tool.3.color != ALPHA_NEUTRAL {
                 tool.3.color = ALPHA_NEUTRAL;
             }
         } else if tool.3.color != ALPHA_RED {
             tool.3.color = ALPHA_RED;
         }
-    } else if mouse.just_pressed(MouseButton::Left) || keyboard.just_pressed(KeyCode::Q) {
+    } else if actions.just_pressed(Action::Nail) {
         let entity = check_package(&ctx, position, &packages);
         if let Some(entity) = entity {
             if let Ok((transform, image, sprite, package)) = packages.get(entity) {
-                let angular_offset = rot_z(transform.rotation);
-                let linear_offset = match package.is_point {
-                    true => Vec2::ZERO,
-                    false => transform.translation.truncate() - position,
-                };
-
-                set_tool(
+                pick_up(
+                    &mut tool.0,
                     &mut tool.2,
-                    image,
                     &mut tool.1,
-                    transform,
                     &mut tool.3,
+                    entity,
+                    transform,
+                    image,
                     sprite,
-                    linear_offset,
-                    angular_offset,
+                    package.is_point,
                 );
-
-                tool.0.item = Some(SelectedItem {
-                    entity,
-                    linear_offset,
-                    angular_offset,
-                })
             }
         }
     }
 
-    if (mouse.just_pressed(MouseButton::Right) || keyboard.just_pressed(KeyCode::E))
-        && tool.0.item.is_some()
-    {
+    if actions.just_pressed(Action::Cancel) && tool.0.item.is_some() {
         tool.0.item = None;
         unset_tool(&mut tool.2, &(tool.4 .0), &mut tool.1, &mut tool.3);
     }
@@ -161,7 +209,39 @@ pub fn update_state(
     None
 }
 
-fn check_package<Q: WorldQuery, F: WorldQuery>(
+/// Holds `entity` in the tool, grabbed where the tool is.
+#[allow(clippy::too_many_arguments)]
+pub fn pick_up(
+    nailgun: &mut Nailgun,
+    tool_image: &mut Handle<Image>,
+    tool_transform: &mut Transform,
+    tool_sprite: &mut Sprite,
+    entity: Entity,
+    transform: &Transform,
+    image: &Handle<Image>,
+    sprite: &Sprite,
+    is_point: bool,
+) {
+    let position = tool_transform.translation.truncate();
+    let angular_offset = rot_z(transform.rotation);
+    let linear_offset = match is_point {
+        true => Vec2::ZERO,
+        false => transform.translation.truncate() - position,
+    };
+
+    let item = SelectedItem {
+        entity,
+        linear_offset,
+        angular_offset,
+        is_point,
+        flipped: false,
+    };
+    set_tool(tool_image, image, tool_sprite, sprite);
+    pose_tool(tool_transform, tool_sprite, &item);
+    nailgun.item = Some(item);
+}
+
+pub fn check_package<Q: WorldQuery, F: WorldQuery>(
     ctx: &RapierContext,
     position: Vec2,
     packages: &Query<Q, F>,
@@ -182,17 +262,17 @@ fn check_anchor_point(
     ctx: &RapierContext,
     anchorable: &Query<(), With<Anchorable>>,
     position: Vec2,
-) -> bool {
-    let mut is_anchor = false;
+) -> Option<Entity> {
+    let mut anchor = None;
     ctx.intersections_with_point(
         position,
         QueryFilter::new().predicate(&|e| anchorable.contains(e)),
-        |_| {
-            is_anchor = true;
+        |e| {
+            anchor = Some(e);
             false
         },
     );
-    is_anchor
+    anchor
 }
 
 fn check_anchor_shape(
@@ -201,19 +281,19 @@ fn check_anchor_shape(
     collider: &Collider,
     pos: Vec2,
     rot: f32,
-) -> bool {
-    let mut is_anchor = false;
+) -> Option<Entity> {
+    let mut anchor = None;
     ctx.intersections_with_shape(
         pos,
         rot,
         collider,
         QueryFilter::new().predicate(&|e| anchorable.contains(e)),
-        |_| {
-            is_anchor = true;
+        |e| {
+            anchor = Some(e);
             false
         },
     );
-    is_anchor
+    anchor
 }
 
 fn check_placeable(
@@ -240,26 +320,26 @@ fn check_placeable(
 const ALPHA_RED: Color = Color::rgba(1., 0., 0., 0.3);
 const ALPHA_NEUTRAL: Color = Color::rgba(1., 1., 1., 0.3);
 
-#[allow(clippy::too_many_arguments)]
 fn set_tool(
     tool_image: &mut Handle<Image>,
     image: &Handle<Image>,
-    tool_transform: &mut Transform,
-    transform: &Transform,
     tool_sprite: &mut Sprite,
     sprite: &Sprite,
-    linear_offset: Vec2,
-    angular_offset: f32,
 ) {
-    tool_transform.rotation = transform.rotation;
     *tool_image = image.clone();
-    let size = sprite.custom_size.unwrap();
-    let anchor = Vec2::from_angle(-angular_offset).rotate(-linear_offset);
-    tool_sprite.anchor = Anchor::Custom(anchor / size);
     tool_sprite.color = ALPHA_NEUTRAL;
     tool_sprite.custom_size = sprite.custom_size;
 }
 
+/// Turns and mirrors the tool's sprite to show the held item where it would be nailed.
+pub fn pose_tool(tool_transform: &mut Transform, tool_sprite: &mut Sprite, item: &SelectedItem) {
+    tool_transform.rotation = Quat::from_rotation_z(item.angular_offset);
+    let size = tool_sprite.custom_size.unwrap();
+    let anchor = Vec2::from_angle(-item.angular_offset).rotate(-item.linear_offset);
+    tool_sprite.anchor = Anchor::Custom(anchor / size);
+    tool_sprite.flip_x = item.flipped;
+}
+
 fn unset_tool(
     tool_image: &mut Handle<Image>,
     backup_image: &Handle<Image>,
@@ -270,81 +350,293 @@ fn unset_tool(
     *tool_image = backup_image.clone();
     tool_sprite.anchor = Anchor::BottomLeft;
     tool_sprite.color = Color::WHITE;
+    tool_sprite.flip_x = false;
     tool_sprite.custom_size = Some(Vec2::new(50., 50.));
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn nail(
-    In(position): In<Option<Vec2>>,
+    In(target): In<Option<(Vec2, Entity)>>,
     mut commands: Commands,
-    chassis: Query<(Entity, &Transform), With<Chassis>>,
+    anchors: Query<&Transform, (With<Anchorable>, Without<Package>)>,
     mut tool: Query<&mut Nailgun>,
-    mut packages: Query<(&mut Transform, &Package), Without<Chassis>>,
+    mut packages: Query<(&mut Transform, &Package)>,
     mut z_sequencer: ResMut<ZSequencer>,
+    config: Res<AttachConfig>,
+    mut money: ResMut<Money>,
+    mut history: ResMut<NailHistory>,
 ) {
-    if let Some(position) = position {
+    if let Some((position, anchor)) = target {
         let tool = &mut tool.single_mut();
         let item = &mut tool.item.as_ref().unwrap();
-        let (chassis_entity, chassis_transform) = chassis.single();
+        let Ok(anchor_transform) = anchors.get(anchor) else {
+            tool.item = None;
+            return;
+        };
 
-        let angular_offset = rot_z(chassis_transform.rotation);
-        let linear_offset = chassis_transform.translation.truncate() - position;
+        // The joint is anchored in the frame of whatever the item is nailed onto
+        let angular_offset = rot_z(anchor_transform.rotation);
+        let linear_offset = anchor_transform.translation.truncate() - position;
 
         let (mut package_transform, package) = packages.get_mut(item.entity).unwrap();
 
-        let joint = match package.is_point {
-            true => revolute_joint(
-                item.linear_offset,
-                linear_offset,
-                item.angular_offset,
-                angular_offset,
-            ),
-            false => fixed_joint(
-                item.linear_offset,
-                linear_offset,
-                item.angular_offset,
-                angular_offset,
-            ),
+        let mode = tool.mode.for_item(package.is_point);
+        let joint = config.joint(
+            mode,
+            item.linear_offset,
+            linear_offset,
+            item.angular_offset,
+            angular_offset,
+        );
+
+        let cargo = Cargo {
+            name: package.name.clone(),
+            price: package.price,
+            powered: package.powered,
+            flipped: item.flipped,
         };
+        // A rejected item is dropped where it is
+        if let Err(e) = attach(
+            &mut commands.entity(item.entity),
+            anchor,
+            joint,
+            mode,
+            cargo,
+        ) {
+            warn!("Can't nail {}: {e}", package.name);
+        } else {
+            money.0 = money.0.saturating_sub(config.cost(mode));
+            money.save();
+            history.push(item.entity, config.cost(mode));
+            package_transform.rotation = Quat::from_rotation_z(item.angular_offset);
+            package_transform.translation =
+                (position + item.linear_offset).extend(z_sequencer.next());
+        }
 
-        package_transform.rotation = Quat::from_rotation_z(item.angular_offset);
-        package_transform.translation = (position + item.linear_offset).extend(z_sequencer.next());
+        tool.item = None;
+    }
+}
 
+/// Turns a package into cargo nailed onto `parent`, a joint that fails validation leaves it loose.
+pub fn attach(
+    commands: &mut EntityCommands,
+    parent: Entity,
+    joint: GenericJoint,
+    mode: AttachMode,
+    cargo: Cargo,
+) -> Result<(), &'static str> {
+    validate_joint(&joint)?;
+    // Rapier panics when links are added to or taken off a multibody that has already been
+    // simulated, so cargo never joins the car's multibody
+    commands.insert(ImpulseJoint::new(parent, joint));
+    if cargo.powered {
         commands
-            .entity(item.entity)
-            .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
-            .insert(MultibodyJoint::new(chassis_entity, joint))
-            .insert(Anchorable)
-            .remove::<Package>();
+            .insert(Wheel {
+                front: joint.local_anchor1().x > 0.,
+                driven: true,
+            })
+            .insert(ExternalForce::default())
+            .insert(Velocity::default());
+    }
+    commands
+        .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
+        .insert(Anchorable)
+        .insert(Nailed { mode })
+        .insert(cargo)
+        .remove::<Package>();
+    Ok(())
+}
 
-        tool.item = None;
+/// Turns nailed cargo back into a loose package, the reverse of `attach`.
+pub fn detach(
+    commands: &mut EntityCommands,
+    preset: &Preset,
+    durability: Option<&Durability>,
+    asset_server: &AssetServer,
+) {
+    preset.insert(commands, asset_server);
+    // The preset's plain sprite would hide the damage until the next hit
+    if let Some(durability) = durability {
+        commands.insert(Sprite {
+            color: durability.shade(),
+            custom_size: Some(preset.size),
+            ..Default::default()
+        });
+        if let Some(image) = durability.damaged_image() {
+            commands.insert(image.clone());
+        }
     }
+    commands
+        .insert(CollisionGroups::new(
+            LOOSE_ITEMS,
+            SOLID_TERRAIN | LOOSE_ITEMS | PLAYER,
+        ))
+        .remove::<ImpulseJoint>()
+        .remove::<Anchorable>()
+        .remove::<Nailed>()
+        .remove::<Cargo>()
+        .remove::<Wheel>()
+        .remove::<ExternalForce>();
 }
 
-fn fixed_joint(a1: Vec2, a2: Vec2, b1: f32, b2: f32) -> GenericJoint {
-    let mut joint = FixedJoint::new();
+/// How cargo was nailed, the joint itself says onto what.
+#[derive(Debug, Clone, Copy, Component)]
+pub struct Nailed {
+    pub mode: AttachMode,
+}
 
-    let a1 = a1.rotate(Vec2::from_angle(-b1));
-    let a2 = a2.rotate(Vec2::from_angle(-b2));
+/// Catches joints rapier would panic on or blow up with instead of rejecting.
+pub fn validate_joint(joint: &GenericJoint) -> Result<(), &'static str> {
+    let frames = [joint.local_anchor1(), joint.local_anchor2()];
+    if !frames.iter().all(|anchor| anchor.is_finite())
+        || !joint.local_basis1().is_finite()
+        || !joint.local_basis2().is_finite()
+    {
+        return Err("joint frame is not finite");
+    }
+    [JointAxis::X, JointAxis::Y, JointAxis::AngX]
+        .into_iter()
+        .try_for_each(|axis| {
+            if let Some(limits) = joint.limits(axis) {
+                if limits.min.is_nan() || limits.max.is_nan() || limits.min > limits.max {
+                    return Err("joint limits are inverted");
+                }
+            }
+            if let Some(motor) = joint.motor(axis) {
+                let values = [
+                    motor.target_pos,
+                    motor.target_vel,
+                    motor.stiffness,
+                    motor.damping,
+                ];
+                if !values.iter().all(|v| v.is_finite())
+                    || motor.stiffness < 0.
+                    || motor.damping < 0.
+                {
+                    return Err("joint motor is invalid");
+                }
+            }
+            Ok(())
+        })
+}
 
-    joint.set_local_anchor1(-a2);
-    joint.set_local_anchor2(-a1);
-    joint.set_local_basis1(-b2);
-    joint.set_local_basis2(-b1);
+#[cfg(test)]
+mod tests {
+    use bevy_rapier2d::rapier::prelude::JointAxesMask;
 
-    joint.into()
-}
+    use super::*;
+
+    fn cargo() -> Cargo {
+        Cargo {
+            name: "Bonus Wheel".to_string(),
+            price: 1,
+            powered: false,
+            flipped: false,
+        }
+    }
 
-// todo mini 24.08.2022 - figure out why revolute joint crashes
-fn revolute_joint(a1: Vec2, a2: Vec2, b1: f32, b2: f32) -> GenericJoint {
-    let mut joint = GenericJoint::new(JointAxesMask::LOCKED_REVOLUTE_AXES);
+    #[test]
+    fn hinged_point_item_spins_freely() {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .add_plugin(TransformPlugin)
+            .add_plugin(HierarchyPlugin)
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::pixels_per_meter(100.))
+            .insert_resource(RapierConfiguration {
+                timestep_mode: TimestepMode::Fixed {
+                    dt: 1. / 60.,
+                    substeps: 1,
+                },
+                ..Default::default()
+            });
+
+        let chassis = app
+            .world
+            .spawn()
+            .insert_bundle(TransformBundle::default())
+            .insert(RigidBody::Fixed)
+            .insert(Collider::cuboid(80., 20.))
+            .id();
+        let nail = Vec2::new(0., 50.);
+        let item = app
+            .world
+            .spawn()
+            .insert_bundle(TransformBundle::from(Transform::from_translation(
+                nail.extend(0.),
+            )))
+            .insert(RigidBody::Dynamic)
+            .insert(Collider::ball(15.))
+            .insert(Velocity::angular(10.))
+            .id();
+
+        // Point items are held at their center, the chassis sits at the origin
+        let joint = AttachConfig::default().joint(AttachMode::Hinge, Vec2::ZERO, -nail, 0., 0.);
+        app.add_startup_system(move |mut commands: Commands| {
+            attach(
+                &mut commands.entity(item),
+                chassis,
+                joint,
+                AttachMode::Hinge,
+                cargo(),
+            )
+            .unwrap();
+        });
+
+        (0..120).for_each(|_| app.update());
+
+        assert!(app.world.get::<ImpulseJoint>(item).is_some());
+        let velocity = app.world.get::<Velocity>(item).unwrap();
+        assert!(velocity.angvel > 5., "stopped spinning: {velocity:?}");
+        let position = app.world.get::<Transform>(item).unwrap().translation;
+        assert!(
+            position.truncate().distance(nail) < 1.,
+            "came off the nail: {position}"
+        );
+    }
 
-    let a1 = a1.rotate(Vec2::from_angle(-b1));
-    let a2 = a2.rotate(Vec2::from_angle(-b2));
+    #[test]
+    fn validate_joint_accepts_every_mode() {
+        let config = AttachConfig::default();
+        [
+            AttachMode::Weld,
+            AttachMode::Hinge,
+            AttachMode::Rope,
+            AttachMode::Spring,
+            AttachMode::Slider,
+        ]
+        .into_iter()
+        .for_each(|mode| {
+            let joint = config.joint(mode, Vec2::new(10., 5.), Vec2::new(-20., 0.), 0.3, -0.2);
+            assert_eq!(validate_joint(&joint), Ok(()), "{mode:?}");
+        });
+    }
 
-    joint.set_local_anchor1(-a2);
-    joint.set_local_anchor2(-a1);
-    joint.set_local_basis1(-b2);
-    joint.set_local_basis2(-b1);
+    #[test]
+    fn validate_joint_rejects_non_finite_anchors() {
+        let mut joint = GenericJoint::new(JointAxesMask::LOCKED_REVOLUTE_AXES);
+        joint.set_local_anchor1(Vec2::new(f32::NAN, 0.));
+        assert!(validate_joint(&joint).is_err());
 
-    joint
+        let mut joint = GenericJoint::new(JointAxesMask::LOCKED_REVOLUTE_AXES);
+        joint.set_local_anchor2(Vec2::new(0., f32::INFINITY));
+        assert!(validate_joint(&joint).is_err());
+    }
+
+    #[test]
+    fn validate_joint_rejects_inverted_limits() {
+        let mut joint = GenericJoint::new(JointAxesMask::LOCKED_PRISMATIC_AXES);
+        joint.set_limits(JointAxis::X, [10., -10.]);
+        assert!(validate_joint(&joint).is_err());
+    }
+
+    #[test]
+    fn validate_joint_rejects_negative_motor_gains() {
+        let mut joint = GenericJoint::new(JointAxesMask::ANG_X);
+        joint.set_motor_position(JointAxis::X, 0., -1., 10.);
+        assert!(validate_joint(&joint).is_err());
+
+        let mut joint = GenericJoint::new(JointAxesMask::ANG_X);
+        joint.set_motor_position(JointAxis::X, 0., 10., -1.);
+        assert!(validate_joint(&joint).is_err());
+    }
 }
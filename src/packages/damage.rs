@@ -0,0 +1,186 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+use rand::Rng;
+
+use crate::{
+    collision_groups::*,
+    map::seed::{streams, WorldSeed},
+    nailgun::{graph::JointGraph, tool::detach},
+};
+
+use super::presets::{Cargo, PresetList, Presets};
+
+#[derive(Debug)]
+pub struct DamageConfig {
+    /// Impulses below this are resting contacts and do no harm.
+    pub impulse_threshold: f32,
+    pub debris: u32,
+    pub debris_lifetime: f32,
+}
+
+impl Default for DamageConfig {
+    fn default() -> Self {
+        Self {
+            impulse_threshold: 5.,
+            debris: 6,
+            debris_lifetime: 3.,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Component)]
+pub struct Durability {
+    pub max: f32,
+    pub current: f32,
+    /// Damage taken per unit of impulse above the threshold.
+    pub fragility: f32,
+    /// Swapped in once half of the durability is gone.
+    pub damaged_sprite: Option<Handle<Image>>,
+}
+
+impl Durability {
+    pub fn fraction(&self) -> f32 {
+        (self.current / self.max).clamp(0., 1.)
+    }
+
+    /// Darkens the sprite as the package gets damaged.
+    pub fn shade(&self) -> Color {
+        let shade = 0.4 + 0.6 * self.fraction();
+        Color::rgb(shade, shade, shade)
+    }
+
+    /// Shown instead of the preset's sprite once half of the durability is gone.
+    pub fn damaged_image(&self) -> Option<&Handle<Image>> {
+        self.damaged_sprite
+            .as_ref()
+            .filter(|_| self.fraction() < 0.5)
+    }
+
+    /// Price after the damage taken so far.
+    pub fn value(&self, price: u32) -> u32 {
+        (price as f32 * self.fraction()).round() as u32
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct Debris(Timer);
+
+/// The rapier wrapper can't enable contact force events yet,
+/// so impulses are read off the contact manifolds instead.
+pub fn take_damage(
+    config: Res<DamageConfig>,
+    ctx: Res<RapierContext>,
+    mut packages: Query<(Entity, &mut Durability)>,
+) {
+    packages.for_each_mut(|(entity, mut durability)| {
+        let impulse = ctx
+            .contacts_with(entity)
+            .flat_map(|pair| {
+                pair.manifolds()
+                    .flat_map(|m| m.points().map(|p| p.impulse()).collect::<Vec<_>>())
+                    .collect::<Vec<_>>()
+            })
+            .fold(0., f32::max);
+        if impulse > config.impulse_threshold {
+            durability.current -= (impulse - config.impulse_threshold) * durability.fragility;
+        }
+    });
+}
+
+pub fn show_damage(
+    mut packages: Query<(&Durability, &mut Sprite, &mut Handle<Image>), Changed<Durability>>,
+) {
+    packages.for_each_mut(|(durability, mut sprite, mut image)| {
+        sprite.color = durability.shade();
+        if let Some(damaged) = durability.damaged_image() {
+            if *image != *damaged {
+                *image = damaged.clone();
+            }
+        }
+    });
+}
+
+/// Broken packages turn into short lived debris, the cargo stacked on them comes loose piece by piece.
+#[allow(clippy::too_many_arguments)]
+pub fn break_packages(
+    mut commands: Commands,
+    config: Res<DamageConfig>,
+    seed: Res<WorldSeed>,
+    graph: Res<JointGraph>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
+    asset_server: Res<AssetServer>,
+    packages: Query<(Entity, &Durability, &Transform, &Sprite)>,
+    cargo: Query<&Cargo>,
+) {
+    packages.for_each(|(entity, durability, transform, sprite)| {
+        if durability.current > 0. {
+            return;
+        }
+        // Everything nailed onto it falls apart, a loose package can't hold cargo of its own
+        graph.descendants(entity).iter().for_each(|child| {
+            let Ok((_, child_durability, ..)) = packages.get(*child) else {
+                return;
+            };
+            if child_durability.current <= 0. {
+                return;
+            }
+            let preset = cargo
+                .get(*child)
+                .ok()
+                .zip(lists.get(&presets.0))
+                .and_then(|(cargo, list)| list.by_name(&cargo.name));
+            match preset {
+                Some(preset) => detach(
+                    &mut commands.entity(*child),
+                    preset,
+                    Some(child_durability),
+                    &asset_server,
+                ),
+                // Without its preset it can't turn back into a package, so it goes down too
+                None => {
+                    commands.entity(*child).insert(Durability {
+                        current: 0.,
+                        ..child_durability.clone()
+                    });
+                }
+            }
+        });
+        commands.entity(entity).despawn_recursive();
+        let size = sprite.custom_size.unwrap_or(Vec2::splat(32.)) / 3.;
+        let mut rng = seed.rng_at(streams::DEBRIS, entity.id() as i64);
+        (0..config.debris).for_each(|_| {
+            let offset = Vec2::new(rng.gen_range(-1. ..1.), rng.gen_range(-1. ..1.)) * size;
+            let velocity = Vec2::new(rng.gen_range(-150. ..150.), rng.gen_range(50. ..250.));
+            commands
+                .spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: sprite.color,
+                        custom_size: Some(size),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_translation(
+                        transform.translation + offset.extend(0.),
+                    ),
+                    ..Default::default()
+                })
+                .insert(RigidBody::Dynamic)
+                .insert(Collider::cuboid(size.x / 2., size.y / 2.))
+                .insert(CollisionGroups::new(LOOSE_ITEMS, SOLID_TERRAIN))
+                .insert(Velocity::linear(velocity))
+                .insert(Debris(Timer::from_seconds(config.debris_lifetime, false)));
+        });
+    });
+}
+
+pub fn clear_debris(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut debris: Query<(Entity, &mut Debris)>,
+) {
+    debris.for_each_mut(|(entity, mut debris)| {
+        if debris.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    });
+}
@@ -0,0 +1,201 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+
+use crate::{
+    map::{
+        chunk::{Chunk, ChunkGen},
+        features::ChunkMember,
+        terrain::TerrainGenerator,
+    },
+    nailgun::graph::JointGraph,
+    player::car::Chassis,
+    utils::storage,
+};
+
+use super::{damage::Durability, presets::Cargo};
+
+#[derive(Debug)]
+pub struct DeliveryConfig {
+    /// A depot is placed in every n-th chunk.
+    pub every: i32,
+    pub width: f32,
+}
+
+impl Default for DeliveryConfig {
+    fn default() -> Self {
+        Self {
+            every: 8,
+            width: 400.,
+        }
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct Depot {
+    pub index: i32,
+    pub x: f32,
+    pub width: f32,
+    /// Whether the car was inside last frame, payouts happen on entry.
+    occupied: bool,
+}
+
+/// Money earned over every run, kept between sessions.
+#[derive(Debug)]
+pub struct Money(pub u32);
+
+const MONEY_KEY: &str = "flippingout_money";
+
+impl Money {
+    pub fn save(&self) {
+        if let Err(e) = storage::save(MONEY_KEY, &self.0.to_string()) {
+            error!("Failed to save money: {e}");
+        }
+    }
+}
+
+impl Default for Money {
+    fn default() -> Self {
+        Self(
+            storage::load(MONEY_KEY)
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0),
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct DeliveryEvent {
+    pub depot: i32,
+    pub cargo: Vec<Cargo>,
+    pub payout: u32,
+}
+
+pub fn spawn_depots(
+    mut commands: Commands,
+    config: Res<DeliveryConfig>,
+    gen: Res<ChunkGen>,
+    chunks: Query<(&Chunk, &Transform), Added<Chunk>>,
+) {
+    chunks.for_each(|(chunk, transform)| {
+        let i = chunk.index();
+        if i <= 0 || i % config.every != 0 {
+            return;
+        }
+        let x = transform.translation.x;
+        let y = gen.height(x);
+        commands
+            .spawn_bundle(SpatialBundle::from_transform(Transform::from_xyz(
+                x, y, -0.5,
+            )))
+            .insert(Depot {
+                index: i,
+                x,
+                width: config.width,
+                occupied: false,
+            })
+            .insert(ChunkMember(i))
+            .with_children(|b| {
+                [-config.width / 2., config.width / 2.]
+                    .into_iter()
+                    .for_each(|dx| {
+                        b.spawn_bundle(SpriteBundle {
+                            sprite: Sprite {
+                                color: Color::rgb(0.35, 0.25, 0.15),
+                                custom_size: Some(Vec2::new(8., 200.)),
+                                ..Default::default()
+                            },
+                            transform: Transform::from_xyz(dx, 100., 0.),
+                            ..Default::default()
+                        });
+                    });
+                b.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(1., 0.8, 0.1),
+                        custom_size: Some(Vec2::new(config.width, 30.)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0., 200., 0.),
+                    ..Default::default()
+                });
+            });
+    });
+}
+
+pub fn deliver(
+    mut commands: Commands,
+    mut money: ResMut<Money>,
+    mut depots: Query<&mut Depot>,
+    chassis: Query<(Entity, &Transform), With<Chassis>>,
+    graph: Res<JointGraph>,
+    cargo: Query<(&Cargo, Option<&Durability>)>,
+    mut events: EventWriter<DeliveryEvent>,
+) {
+    let (chassis, transform) = chassis.single();
+    let x = transform.translation.x;
+    depots.for_each_mut(|mut depot| {
+        let inside = (x - depot.x).abs() < depot.width / 2.;
+        if inside && !depot.occupied {
+            // Only what is still nailed on gets paid, cargo lying around elsewhere stays
+            let delivered = graph
+                .descendants(chassis)
+                .into_iter()
+                .filter_map(|entity| {
+                    let (cargo, durability) = cargo.get(entity).ok()?;
+                    commands.entity(entity).despawn_recursive();
+                    Some(Cargo {
+                        price: durability.map_or(cargo.price, |d| d.value(cargo.price)),
+                        ..cargo.clone()
+                    })
+                })
+                .collect::<Vec<_>>();
+            if !delivered.is_empty() {
+                let payout = delivered.iter().map(|c| c.price).sum();
+                money.0 += payout;
+                money.save();
+                events.send(DeliveryEvent {
+                    depot: depot.index,
+                    cargo: delivered,
+                    payout,
+                });
+            }
+        }
+        depot.occupied = inside;
+    });
+}
+
+/// Last delivery and how long it stays on screen.
+#[derive(Debug, Default)]
+pub struct DeliveryMessage(Option<(String, Timer)>);
+
+pub fn show_money(
+    mut egui_context: ResMut<EguiContext>,
+    money: Res<Money>,
+    time: Res<Time>,
+    mut message: ResMut<DeliveryMessage>,
+    mut events: EventReader<DeliveryEvent>,
+) {
+    events.iter().for_each(|event| {
+        let names = event
+            .cargo
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        let text = format!("Depot {}: {} for ${}", event.depot, names, event.payout);
+        message.0 = Some((text, Timer::from_seconds(4., false)));
+    });
+    if let Some((_, timer)) = message.0.as_mut() {
+        if timer.tick(time.delta()).finished() {
+            message.0 = None;
+        }
+    }
+
+    egui::Area::new("money")
+        .anchor(egui::Align2::RIGHT_TOP, [-8., 28.])
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!("Money: ${}", money.0));
+            if let Some((text, _)) = &message.0 {
+                ui.label(text);
+            }
+        });
+}
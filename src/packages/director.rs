@@ -1,16 +1,25 @@
 use bevy::prelude::*;
 use bevy_rapier2d::prelude::{RigidBody, Sleeping};
-use rand::{rngs::SmallRng, SeedableRng};
 
-use crate::{map::chunk::ChunkGen, player::car::Chassis};
+use crate::{
+    map::{
+        cache::RestorePackages,
+        chunk::ChunkGen,
+        seed::{streams, WorldSeed},
+        terrain::TerrainGenerator,
+    },
+    player::car::Chassis,
+};
 
-use super::presets::{Package, Preset};
+use super::{
+    damage::Durability,
+    presets::{Package, PresetList, Presets},
+};
 
 #[derive(Debug)]
 pub struct PackageSpawnerConfig {
     distance_apart: f32,
     spawn_distance: f32,
-    despawn_distance: f32,
     freeze_distance: f32,
 }
 
@@ -19,43 +28,43 @@ impl Default for PackageSpawnerConfig {
         Self {
             distance_apart: 1024.,
             spawn_distance: 2048.,
-            despawn_distance: 8192.,
             freeze_distance: 3072.,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct PackageSpawner {
-    rng: SmallRng,
     last_spawned: u32,
 }
 
-impl Default for PackageSpawner {
-    fn default() -> Self {
-        Self {
-            rng: SmallRng::from_entropy(),
-            last_spawned: 0,
-        }
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 pub fn spawn(
     mut commands: Commands,
     player: Query<&Transform, With<Chassis>>,
     config: Res<PackageSpawnerConfig>,
     mut spawner: ResMut<PackageSpawner>,
     asset_server: Res<AssetServer>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
     gen: Res<ChunkGen>,
+    seed: Res<WorldSeed>,
 ) {
+    let Some(list) = lists.get(&presets.0) else {
+        return;
+    };
     let player_x = player.single().translation.x;
     let last_spawned = spawner.last_spawned as f32 * config.distance_apart;
 
     if player_x + config.spawn_distance > last_spawned {
         spawner.last_spawned += 1;
         let to_spawn = spawner.last_spawned as f32 * config.distance_apart;
-        let y = gen.probe(to_spawn);
-        let preset = Preset::get_random(&mut spawner.rng);
+        let y = gen.height(to_spawn);
+        let mut rng = seed.rng_at(streams::PACKAGES, spawner.last_spawned as i64);
+        let biome = gen.biomes().sample(to_spawn).dominant();
+        let Some(preset) = list.get_random(&mut rng, biome) else {
+            return;
+        };
         let mut entity = commands.spawn_bundle(TransformBundle::from(Transform::from_xyz(
             to_spawn,
             y + 100.,
@@ -66,20 +75,37 @@ pub fn spawn(
     }
 }
 
-#[allow(clippy::type_complexity)]
-pub fn despawn(
+pub fn restore(
     mut commands: Commands,
-    player: Query<&Transform, (With<Chassis>, Without<Package>)>,
-    config: Res<PackageSpawnerConfig>,
-    packages: Query<(Entity, &Transform), (With<Package>, Without<Chassis>)>,
+    mut events: EventReader<RestorePackages>,
+    asset_server: Res<AssetServer>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
 ) {
-    let player_x = player.single().translation.x;
-
-    packages.for_each(|(entity, package_transform)| {
-        if player_x - package_transform.translation.x > config.despawn_distance {
-            commands.entity(entity).despawn_recursive();
-        }
-    })
+    // Leave the events for later frames until the presets are in
+    let Some(list) = lists.get(&presets.0) else {
+        return;
+    };
+    events
+        .iter()
+        .flat_map(|e| &e.0)
+        .for_each(|state| match list.by_name(&state.preset) {
+            Some(preset) => {
+                let mut entity = commands.spawn_bundle(TransformBundle::from(
+                    Transform::from_translation(state.position.extend(0.))
+                        .with_rotation(Quat::from_rotation_z(state.angle)),
+                ));
+                entity.insert(Sleeping::default());
+                preset.apply(&mut entity, &asset_server);
+                if let Some(current) = state.durability {
+                    entity.insert(Durability {
+                        current,
+                        ..preset.durability(&asset_server)
+                    });
+                }
+            }
+            None => warn!("Unknown package preset {}", state.preset),
+        });
 }
 
 #[derive(Debug, Component)]
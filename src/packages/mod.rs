@@ -1,19 +1,55 @@
-use bevy::prelude::Plugin;
+use bevy::prelude::{AddAsset, ParallelSystemDescriptorCoercion, Plugin, ResMut, SystemSet};
 
-use self::director::{defroster, despawn, freezer, spawn, PackageSpawner, PackageSpawnerConfig};
+use crate::run::GameState;
 
+use self::{
+    damage::{break_packages, clear_debris, show_damage, take_damage, DamageConfig},
+    delivery::{
+        deliver, show_money, spawn_depots, DeliveryConfig, DeliveryEvent, DeliveryMessage, Money,
+    },
+    director::{defroster, freezer, restore, spawn, PackageSpawner, PackageSpawnerConfig},
+    presets::{reload, PresetList, PresetLoader, Presets},
+};
+
+pub mod damage;
+pub mod delivery;
 pub mod director;
 pub mod presets;
 
+fn init(mut spawner: ResMut<PackageSpawner>, mut message: ResMut<DeliveryMessage>) {
+    *spawner = PackageSpawner::default();
+    *message = DeliveryMessage::default();
+}
+
 pub struct PackagePlugin;
 
 impl Plugin for PackagePlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.init_resource::<PackageSpawnerConfig>()
+        app.add_asset::<PresetList>()
+            .init_asset_loader::<PresetLoader>()
+            .init_resource::<Presets>()
+            .init_resource::<PackageSpawnerConfig>()
             .init_resource::<PackageSpawner>()
-            .add_system(spawn)
-            .add_system(despawn)
-            .add_system(freezer)
-            .add_system(defroster);
+            .init_resource::<DamageConfig>()
+            .init_resource::<DeliveryConfig>()
+            .init_resource::<Money>()
+            .init_resource::<DeliveryMessage>()
+            .add_event::<DeliveryEvent>()
+            .add_system(reload)
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(init))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn)
+                    .with_system(restore)
+                    .with_system(freezer)
+                    .with_system(defroster)
+                    .with_system(spawn_depots)
+                    .with_system(deliver)
+                    .with_system(show_money)
+                    .with_system(take_damage)
+                    .with_system(show_damage.after(take_damage))
+                    .with_system(break_packages.after(take_damage))
+                    .with_system(clear_debris),
+            );
     }
 }
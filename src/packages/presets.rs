@@ -1,61 +1,244 @@
-use bevy::{ecs::system::EntityCommands, prelude::*};
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    ecs::system::EntityCommands,
+    prelude::*,
+    reflect::TypeUuid,
+};
 use bevy_rapier2d::prelude::*;
 use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::Deserialize;
 
-use crate::collision_groups::*;
+use crate::{collision_groups::*, map::biome::Biome};
 
+use super::damage::Durability;
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Shape {
+    Ball {
+        radius: f32,
+    },
+    Cuboid {
+        half_width: f32,
+        half_height: f32,
+    },
+    RoundCuboid {
+        half_width: f32,
+        half_height: f32,
+        border_radius: f32,
+    },
+}
+
+impl Shape {
+    fn collider(&self) -> Collider {
+        match *self {
+            Shape::Ball { radius } => Collider::ball(radius),
+            Shape::Cuboid {
+                half_width,
+                half_height,
+            } => Collider::cuboid(half_width, half_height),
+            Shape::RoundCuboid {
+                half_width,
+                half_height,
+                border_radius,
+            } => Collider::round_cuboid(half_width, half_height, border_radius),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum Combine {
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl From<Combine> for CoefficientCombineRule {
+    fn from(combine: Combine) -> Self {
+        match combine {
+            Combine::Average => CoefficientCombineRule::Average,
+            Combine::Min => CoefficientCombineRule::Min,
+            Combine::Multiply => CoefficientCombineRule::Multiply,
+            Combine::Max => CoefficientCombineRule::Max,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Coefficient {
+    pub coefficient: f32,
+    pub combine: Combine,
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct Preset {
-    chance: u32,
-    factory: for<'w, 's, 'a, 'b, 'c> fn(
-        &'b mut EntityCommands<'w, 's, 'a>,
-        &'c AssetServer,
-    ) -> &'b mut EntityCommands<'w, 's, 'a>,
+    pub name: String,
+    pub price: u32,
+    pub shape: Shape,
+    /// Size the sprite is drawn at.
+    pub size: Vec2,
+    pub mass: f32,
+    #[serde(default)]
+    pub friction: Option<Coefficient>,
+    #[serde(default)]
+    pub restitution: Option<Coefficient>,
+    pub sprite: String,
+    /// Spawn weight before the biome multipliers.
+    pub chance: u32,
+    /// Nailed at a single point and free to spin around it.
+    #[serde(default)]
+    pub is_point: bool,
+    /// A nailed point item is driven by the drivetrain like a wheel.
+    #[serde(default)]
+    pub powered: bool,
+    #[serde(default = "default_durability")]
+    pub durability: f32,
+    #[serde(default = "default_fragility")]
+    pub fragility: f32,
+    #[serde(default)]
+    pub damaged_sprite: Option<String>,
+}
+
+fn default_durability() -> f32 {
+    100.
+}
+
+fn default_fragility() -> f32 {
+    1.
 }
 
 impl Preset {
-    pub fn get_random(rng: &mut impl Rng) -> &Preset {
-        let dist = WeightedIndex::new(PRESETS.map(|p| p.chance)).unwrap();
-        &PRESETS[dist.sample(rng)]
+    pub fn apply<'w, 's, 'a, 'b>(
+        &self,
+        commands: &'b mut EntityCommands<'w, 's, 'a>,
+        asset_server: &AssetServer,
+    ) -> &'b mut EntityCommands<'w, 's, 'a> {
+        base_factory(commands);
+        commands.insert(self.durability(asset_server));
+        self.insert(commands, asset_server)
     }
 
-    pub fn apply<'w, 's, 'a, 'b, 'c>(
+    pub fn durability(&self, asset_server: &AssetServer) -> Durability {
+        Durability {
+            max: self.durability,
+            current: self.durability,
+            fragility: self.fragility,
+            damaged_sprite: self
+                .damaged_sprite
+                .as_ref()
+                .map(|path| asset_server.load(path)),
+        }
+    }
+
+    /// Everything coming from the preset file, also used to update packages on hot reload.
+    pub fn insert<'w, 's, 'a, 'b>(
         &self,
         commands: &'b mut EntityCommands<'w, 's, 'a>,
-        assets_server: &'c AssetServer,
+        asset_server: &AssetServer,
     ) -> &'b mut EntityCommands<'w, 's, 'a> {
-        base_factory(commands);
-        (self.factory)(commands, assets_server)
+        commands
+            .insert(self.shape.collider())
+            .insert(AdditionalMassProperties::Mass(self.mass))
+            .insert(Package {
+                name: self.name.clone(),
+                price: self.price,
+                is_point: self.is_point,
+                powered: self.powered,
+            })
+            .insert(Sprite {
+                custom_size: Some(self.size),
+                ..Default::default()
+            })
+            .insert(asset_server.load::<Image, _>(&self.sprite))
+            .insert(Visibility::default())
+            .insert(ComputedVisibility::default());
+        match self.friction {
+            Some(friction) => commands.insert(Friction {
+                coefficient: friction.coefficient,
+                combine_rule: friction.combine.into(),
+            }),
+            None => commands.remove::<Friction>(),
+        };
+        match self.restitution {
+            Some(restitution) => commands.insert(Restitution {
+                coefficient: restitution.coefficient,
+                combine_rule: restitution.combine.into(),
+            }),
+            None => commands.remove::<Restitution>(),
+        };
+        commands
     }
 }
 
-pub const PRESETS: [Preset; 5] = [
-    Preset {
-        chance: 1,
-        factory: wooden_crate_factory,
-    },
-    Preset {
-        chance: 1,
-        factory: metal_ball_factory,
-    },
-    Preset {
-        chance: 1,
-        factory: beach_ball_factory,
-    },
-    Preset {
-        chance: 1,
-        factory: ice_cube_factory,
-    },
-    Preset {
-        chance: 0,
-        factory: bonus_wheel_factory,
-    },
-];
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "5b3c6f0e-3f7d-4f0a-9a51-6c2f1d8e4b27"]
+pub struct PresetList {
+    pub presets: Vec<Preset>,
+}
+
+impl PresetList {
+    pub fn get_random(&self, rng: &mut impl Rng, biome: Biome) -> Option<&Preset> {
+        let weights = self
+            .presets
+            .iter()
+            .map(|p| p.chance * biome.package_weight(&p.name));
+        let dist = WeightedIndex::new(weights).ok()?;
+        Some(&self.presets[dist.sample(rng)])
+    }
+
+    pub fn by_name(&self, name: &str) -> Option<&Preset> {
+        self.presets.iter().find(|p| p.name == name)
+    }
+}
+
+#[derive(Default)]
+pub struct PresetLoader;
+
+impl AssetLoader for PresetLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let list: PresetList = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(list));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["packages.ron"]
+    }
+}
+
+pub const PRESETS_PATH: &str = "presets.packages.ron";
+
+#[derive(Debug)]
+pub struct Presets(pub Handle<PresetList>);
+
+impl FromWorld for Presets {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<AssetServer>().load(PRESETS_PATH))
+    }
+}
 
 #[derive(Debug, Component)]
 pub struct Package {
-    pub name: &'static str,
+    pub name: String,
     pub price: u32,
     pub is_point: bool,
+    pub powered: bool,
+}
+
+/// What a nailed package was, it stops being a loose `Package` once nailed.
+#[derive(Debug, Clone, Component)]
+pub struct Cargo {
+    pub name: String,
+    pub price: u32,
+    pub powered: bool,
+    /// Drawn mirrored, every preset shape is symmetric so the collider stays the same.
+    pub flipped: bool,
 }
 
 fn base_factory<'w, 's, 'a, 'b>(
@@ -69,120 +252,24 @@ fn base_factory<'w, 's, 'a, 'b>(
         ))
 }
 
-fn wooden_crate_factory<'w, 's, 'a, 'b, 'c>(
-    commands: &'b mut EntityCommands<'w, 's, 'a>,
-    asset_server: &'c AssetServer,
-) -> &'b mut EntityCommands<'w, 's, 'a> {
-    let collider = Collider::cuboid(30., 30.);
-    commands
-        .insert(collider)
-        .insert(AdditionalMassProperties::Mass(6.))
-        .insert(Package {
-            name: "Wooden Crate",
-            price: 1,
-            is_point: false,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(64., 64.)),
-            ..Default::default()
-        })
-        .insert(asset_server.load::<Image, _>("wooden_crate.png"))
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default())
-}
-
-fn metal_ball_factory<'w, 's, 'a, 'b, 'c>(
-    commands: &'b mut EntityCommands<'w, 's, 'a>,
-    asset_server: &'c AssetServer,
-) -> &'b mut EntityCommands<'w, 's, 'a> {
-    let collider = Collider::ball(20.);
-    commands
-        .insert(collider)
-        .insert(AdditionalMassProperties::Mass(10.))
-        .insert(Package {
-            name: "Bowling Ball",
-            price: 3,
-            is_point: false,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(44., 44.)),
-            ..Default::default()
-        })
-        .insert(asset_server.load::<Image, _>("bowling_ball.png"))
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default())
-}
-
-fn beach_ball_factory<'w, 's, 'a, 'b, 'c>(
-    commands: &'b mut EntityCommands<'w, 's, 'a>,
-    asset_server: &'c AssetServer,
-) -> &'b mut EntityCommands<'w, 's, 'a> {
-    let collider = Collider::ball(40.);
-    commands
-        .insert(collider)
-        .insert(AdditionalMassProperties::Mass(1.))
-        .insert(Restitution {
-            coefficient: 0.95,
-            combine_rule: CoefficientCombineRule::Max,
-        })
-        .insert(Package {
-            name: "Beach Ball",
-            price: 3,
-            is_point: false,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(88., 88.)),
-            ..Default::default()
-        })
-        .insert(asset_server.load::<Image, _>("beach_ball.png"))
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default())
-}
-
-fn ice_cube_factory<'w, 's, 'a, 'b, 'c>(
-    commands: &'b mut EntityCommands<'w, 's, 'a>,
-    asset_server: &'c AssetServer,
-) -> &'b mut EntityCommands<'w, 's, 'a> {
-    let collider = Collider::round_cuboid(15., 15., 0.01);
-    commands
-        .insert(collider)
-        .insert(Friction {
-            coefficient: 0.01,
-            combine_rule: CoefficientCombineRule::Min,
-        })
-        .insert(AdditionalMassProperties::Mass(6.))
-        .insert(Package {
-            name: "Ice Cube",
-            price: 1,
-            is_point: false,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(32., 32.)),
-            ..Default::default()
-        })
-        .insert(asset_server.load::<Image, _>("ice_cube.png"))
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default())
-}
-
-fn bonus_wheel_factory<'w, 's, 'a, 'b, 'c>(
-    commands: &'b mut EntityCommands<'w, 's, 'a>,
-    asset_server: &'c AssetServer,
-) -> &'b mut EntityCommands<'w, 's, 'a> {
-    let collider = Collider::ball(30.);
-    commands
-        .insert(collider)
-        .insert(AdditionalMassProperties::Mass(5.))
-        .insert(Package {
-            name: "Bonus Wheel",
-            price: 3,
-            is_point: true,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(66., 66.)),
-            ..Default::default()
-        })
-        .insert(asset_server.load::<Image, _>("wheel1.png"))
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default())
+/// Applies edited preset files to the packages already in the world.
+pub fn reload(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<PresetList>>,
+    lists: Res<Assets<PresetList>>,
+    asset_server: Res<AssetServer>,
+    packages: Query<(Entity, &Package)>,
+) {
+    events.iter().for_each(|event| {
+        if let AssetEvent::Modified { handle } = event {
+            let Some(list) = lists.get(handle) else {
+                return;
+            };
+            packages.for_each(|(entity, package)| {
+                if let Some(preset) = list.by_name(&package.name) {
+                    preset.insert(&mut commands.entity(entity), &asset_server);
+                }
+            });
+        }
+    });
 }
@@ -13,6 +13,10 @@ pub fn init_cam(mut commands: Commands) {
         .insert(Chunkloader);
 }
 
+pub fn reset_cam(mut cam: Query<&mut Transform, With<Chunkloader>>) {
+    cam.single_mut().translation = Vec3::new(0., 0., 10.);
+}
+
 pub fn follow_cam(
     mut cam: Query<&mut Transform, (With<Chunkloader>, Without<Chassis>)>,
     chassis: Query<&Transform, (With<Chassis>, Without<Chunkloader>)>,
@@ -2,147 +2,120 @@ use bevy::{prelude::*, sprite::Anchor};
 use bevy_rapier2d::{
     prelude::{
         AdditionalMassProperties, CoefficientCombineRule, Collider, CollisionGroups, ExternalForce,
-        Friction, GenericJoint, MultibodyJoint, RigidBody,
+        Friction, GenericJoint, MultibodyJoint, RigidBody, Velocity,
     },
-    rapier::prelude::{JointAxesMask, JointAxis},
+    rapier::prelude::JointAxesMask,
 };
 
 use crate::{collision_groups::*, nailgun::tool::Anchorable};
 
+use super::{
+    suspension::Compression,
+    vehicles::{SelectedVehicle, VehicleDef, VehicleList, Vehicles},
+};
+
 #[derive(Debug, Component)]
 pub struct Chassis;
 
 #[derive(Debug, Component)]
-pub struct Wheel;
-
-pub fn spawn_player_car(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let y = 500.;
-    let chassis_texture = asset_server.load::<Image, _>("car.png");
-
-    let cs = 8.;
-    let chassis_bottom = Collider::convex_hull(&[
-        Vec2::new(5.3, 0.) * cs,
-        Vec2::new(10.7, -1.) * cs,
-        Vec2::new(10.7, -4.) * cs,
-        Vec2::new(-11., -4.) * cs,
-        Vec2::new(-11., 0.) * cs,
-    ])
-    .unwrap();
+pub struct Wheel {
+    /// Mounted ahead of the chassis center.
+    pub front: bool,
+    /// Whether the vehicle definition drives this wheel.
+    pub driven: bool,
+}
 
-    let chassis_top = Collider::convex_hull(&[
-        Vec2::new(3.8, 3.) * cs,
-        Vec2::new(5.3, 0.) * cs,
-        Vec2::new(-2., 0.) * cs,
-        Vec2::new(-2., 3.) * cs,
-    ])
-    .unwrap();
+pub fn spawn_player_car(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    vehicles: Res<Vehicles>,
+    lists: Res<Assets<VehicleList>>,
+    selected: Res<SelectedVehicle>,
+) {
+    // The menu doesn't start a run before the vehicles are in
+    let Some(vehicle) = lists.get(&vehicles.0).and_then(|l| l.get(&selected.0)) else {
+        error!("No vehicle to spawn");
+        return;
+    };
+    spawn_car(
+        &mut commands,
+        &asset_server,
+        vehicle,
+        Transform::from_xyz(0., 500., 0.),
+    );
+}
 
-    let chassis = Collider::compound(vec![
-        (Vec2::ZERO, 0., chassis_bottom),
-        (Vec2::ZERO, 0., chassis_top),
-    ]);
+/// Spawns the chassis at `transform` with its wheels, returns the chassis.
+pub fn spawn_car(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    vehicle: &VehicleDef,
+    transform: Transform,
+) -> Entity {
+    let hulls = vehicle
+        .hulls
+        .iter()
+        .filter_map(|points| Collider::convex_hull(points))
+        .map(|hull| (Vec2::ZERO, 0., hull))
+        .collect();
 
     let chassis = commands
-        .spawn_bundle(TransformBundle::from(Transform::from_xyz(0., y, 0.)))
+        .spawn_bundle(TransformBundle::from(transform))
         .insert(RigidBody::Dynamic)
-        .insert(chassis)
+        .insert(Collider::compound(hulls))
         .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
         .insert(Chassis)
+        .insert(Velocity::default())
         .insert(ExternalForce::default())
-        .insert(AdditionalMassProperties::Mass(40.))
+        .insert(AdditionalMassProperties::Mass(vehicle.mass))
         .insert(Anchorable)
         .insert(Sprite {
-            custom_size: Some(Vec2::new(180., 60.)),
-            anchor: Anchor::Custom(Vec2::new(0., 0.08)),
+            custom_size: Some(vehicle.size),
+            anchor: Anchor::Custom(vehicle.anchor),
             ..Default::default()
         })
-        .insert(chassis_texture)
+        .insert(asset_server.load::<Image, _>(&vehicle.sprite))
         .insert(Visibility::default())
         .insert(ComputedVisibility::default())
         .id();
 
-    let left_wheel = Collider::ball(15.);
-
-    let left_wheel_image = asset_server.load::<Image, _>("wheel1.png");
-
-    let mut left_joint = GenericJoint::new(JointAxesMask::Y);
-    left_joint.set_local_anchor1(Vec2::new(45., -20.));
-    left_joint.set_local_axis1(Vec2::new(0., 1.));
-    left_joint.set_limits(JointAxis::X, [-20., 0.]);
-    left_joint.set_motor_position(JointAxis::X, -20., 400., 40.);
-
-    commands
-        .spawn_bundle(TransformBundle::from(Transform::from_xyz(45., y - 20., 0.)))
-        .insert(RigidBody::Dynamic)
-        .insert(left_wheel)
-        .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
-        .insert(Wheel)
-        .insert(ExternalForce::default())
-        .insert(AdditionalMassProperties::Mass(10.))
-        .insert(MultibodyJoint::new(chassis, left_joint))
-        .insert(Friction {
-            coefficient: 1.,
-            combine_rule: CoefficientCombineRule::Max,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(34., 34.)),
-            ..Default::default()
-        })
-        .insert(left_wheel_image)
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default());
-
-    let right_wheel = Collider::ball(15.);
-
-    let right_wheel_image = asset_server.load::<Image, _>("wheel2.png");
-
-    let mut right_joint = GenericJoint::new(JointAxesMask::Y);
-    right_joint.set_local_anchor1(Vec2::new(-45., -20.));
-    right_joint.set_local_axis1(Vec2::new(0., 1.));
-    right_joint.set_limits(JointAxis::X, [-20., 0.]);
-    right_joint.set_motor_position(JointAxis::X, -20., 400., 40.);
-
-    commands
-        .spawn_bundle(TransformBundle::from(Transform::from_xyz(
-            -45.,
-            y - 20.,
-            0.,
-        )))
-        .insert(RigidBody::Dynamic)
-        .insert(right_wheel)
-        .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
-        .insert(Wheel)
-        .insert(ExternalForce::default())
-        .insert(AdditionalMassProperties::Mass(10.))
-        .insert(MultibodyJoint::new(chassis, right_joint))
-        .insert(Friction {
-            coefficient: 1.,
-            combine_rule: CoefficientCombineRule::Max,
-        })
-        .insert(Sprite {
-            custom_size: Some(Vec2::new(34., 34.)),
-            ..Default::default()
-        })
-        .insert(right_wheel_image)
-        .insert(Visibility::default())
-        .insert(ComputedVisibility::default());
-}
-
-pub fn movement(
-    mut wheels: Query<&mut ExternalForce, (With<Wheel>, Without<Chassis>)>,
-    mut anchorables: Query<&mut ExternalForce, (With<Anchorable>, Without<Wheel>)>,
-    keyboard_input: Res<Input<KeyCode>>,
-) {
-    let mut delta = 0.;
-
-    if keyboard_input.pressed(KeyCode::A) {
-        delta += 5.0;
-    }
-
-    if keyboard_input.pressed(KeyCode::D) {
-        delta -= 5.0;
-    }
-
-    wheels.for_each_mut(|mut f| f.torque = delta * 3.);
-    anchorables.for_each_mut(|mut f| f.torque = delta * 3.);
+    vehicle.wheels.iter().for_each(|wheel| {
+        let suspension = wheel.suspension();
+        let mut joint = GenericJoint::new(JointAxesMask::Y);
+        joint.set_local_anchor1(wheel.anchor);
+        joint.set_local_axis1(Vec2::new(0., 1.));
+        suspension.apply(&mut joint);
+
+        let offset = wheel.anchor - Vec2::new(0., suspension.rest_length);
+        commands
+            .spawn_bundle(TransformBundle::from(
+                transform.mul_transform(Transform::from_translation(offset.extend(0.))),
+            ))
+            .insert(RigidBody::Dynamic)
+            .insert(Collider::ball(wheel.radius))
+            .insert(CollisionGroups::new(PLAYER, SOLID_TERRAIN | LOOSE_ITEMS))
+            .insert(Wheel {
+                front: wheel.anchor.x > 0.,
+                driven: wheel.drive,
+            })
+            .insert(Velocity::default())
+            .insert(ExternalForce::default())
+            .insert(AdditionalMassProperties::Mass(wheel.mass))
+            .insert(MultibodyJoint::new(chassis, joint))
+            .insert(suspension)
+            .insert(Compression::default())
+            .insert(Friction {
+                coefficient: wheel.friction,
+                combine_rule: CoefficientCombineRule::Multiply,
+            })
+            .insert(Sprite {
+                custom_size: Some(Vec2::splat(wheel.size)),
+                ..Default::default()
+            })
+            .insert(asset_server.load::<Image, _>(&wheel.sprite))
+            .insert(Visibility::default())
+            .insert(ComputedVisibility::default());
+    });
+
+    chassis
 }
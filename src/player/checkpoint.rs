@@ -0,0 +1,290 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    controls::{Action, Actions},
+    map::{
+        chunk::{Chunk, ChunkGen},
+        features::ChunkMember,
+        terrain::TerrainGenerator,
+    },
+    nailgun::{
+        graph::JointGraph,
+        mode::AttachMode,
+        tool::{attach, Nailed},
+    },
+    packages::{
+        damage::Durability,
+        presets::{Cargo, Preset, PresetList, Presets},
+    },
+    utils::quat::rot_z,
+};
+
+use super::{
+    car::{spawn_car, Chassis, Wheel},
+    vehicles::{SelectedVehicle, VehicleList, Vehicles},
+};
+
+#[derive(Debug)]
+pub struct CheckpointConfig {
+    /// A checkpoint is placed in every n-th chunk, half way between depots.
+    pub every: i32,
+    /// Height above the terrain a respawned car is dropped from.
+    pub drop_height: f32,
+}
+
+impl Default for CheckpointConfig {
+    fn default() -> Self {
+        Self {
+            every: 4,
+            drop_height: 80.,
+        }
+    }
+}
+
+#[derive(Debug, Component)]
+pub struct Checkpoint {
+    pub index: i32,
+    pub x: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CargoSnapshot {
+    pub name: String,
+    pub price: u32,
+    pub durability: Option<f32>,
+    /// Relative to the chassis.
+    pub transform: Transform,
+    /// Index of the cargo it is nailed onto, `None` is the chassis.
+    pub parent: Option<usize>,
+    pub mode: AttachMode,
+    pub joint: GenericJoint,
+    pub flipped: bool,
+}
+
+/// The car as it passed a checkpoint.
+#[derive(Debug, Clone)]
+pub struct CarSnapshot {
+    pub checkpoint: i32,
+    pub x: f32,
+    pub velocity: Velocity,
+    pub cargo: Vec<CargoSnapshot>,
+}
+
+/// Without a snapshot the car respawns empty at the start.
+#[derive(Debug, Default)]
+pub struct LastCheckpoint(pub Option<CarSnapshot>);
+
+#[derive(Debug)]
+pub struct RespawnEvent {
+    pub keep_cargo: bool,
+}
+
+pub fn init(mut checkpoint: ResMut<LastCheckpoint>) {
+    *checkpoint = LastCheckpoint::default();
+}
+
+pub fn spawn_checkpoints(
+    mut commands: Commands,
+    config: Res<CheckpointConfig>,
+    gen: Res<ChunkGen>,
+    chunks: Query<(&Chunk, &Transform), Added<Chunk>>,
+) {
+    chunks.for_each(|(chunk, transform)| {
+        let i = chunk.index();
+        if i <= 0 || i % config.every != config.every / 2 {
+            return;
+        }
+        let x = transform.translation.x;
+        let y = gen.height(x);
+        commands
+            .spawn_bundle(SpatialBundle::from_transform(Transform::from_xyz(
+                x, y, -0.5,
+            )))
+            .insert(Checkpoint { index: i, x })
+            .insert(ChunkMember(i))
+            .with_children(|b| {
+                b.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.3, 0.3, 0.3),
+                        custom_size: Some(Vec2::new(6., 160.)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(0., 80., 0.),
+                    ..Default::default()
+                });
+                b.spawn_bundle(SpriteBundle {
+                    sprite: Sprite {
+                        color: Color::rgb(0.1, 0.7, 0.2),
+                        custom_size: Some(Vec2::new(50., 30.)),
+                        ..Default::default()
+                    },
+                    transform: Transform::from_xyz(28., 145., 0.),
+                    ..Default::default()
+                });
+            });
+    });
+}
+
+/// Snapshots the car when it passes a new checkpoint on its wheels.
+#[allow(clippy::type_complexity)]
+pub fn take_snapshot(
+    mut last: ResMut<LastCheckpoint>,
+    checkpoints: Query<&Checkpoint>,
+    chassis: Query<(Entity, &Transform, &Velocity), With<Chassis>>,
+    graph: Res<JointGraph>,
+    cargo: Query<(
+        &Transform,
+        &Cargo,
+        &Nailed,
+        &ImpulseJoint,
+        Option<&Durability>,
+    )>,
+) {
+    let Some((chassis_entity, chassis, velocity)) = chassis.iter().next() else {
+        return;
+    };
+    if rot_z(chassis.rotation).abs() > FRAC_PI_2 {
+        return;
+    }
+    let passed = last.0.as_ref().map_or(0, |s| s.checkpoint);
+    let Some(checkpoint) = checkpoints
+        .iter()
+        .filter(|c| c.index > passed && chassis.translation.x > c.x)
+        .max_by_key(|c| c.index)
+    else {
+        return;
+    };
+
+    // Parents come before the cargo nailed onto them
+    let nailed = graph
+        .descendants(chassis_entity)
+        .into_iter()
+        .filter_map(|entity| cargo.get(entity).ok().map(|c| (entity, c)))
+        .collect::<Vec<_>>();
+    let indices = nailed
+        .iter()
+        .enumerate()
+        .map(|(i, (entity, ..))| (*entity, i))
+        .collect::<HashMap<_, _>>();
+    let to_chassis = chassis.compute_matrix().inverse();
+    let cargo = nailed
+        .into_iter()
+        .map(
+            |(_, (transform, cargo, nailed, joint, durability))| CargoSnapshot {
+                name: cargo.name.clone(),
+                price: cargo.price,
+                durability: durability.map(|d| d.current),
+                transform: Transform::from_matrix(to_chassis * transform.compute_matrix()),
+                parent: indices.get(&joint.parent).copied(),
+                mode: nailed.mode,
+                joint: joint.data,
+                flipped: cargo.flipped,
+            },
+        )
+        .collect();
+    last.0 = Some(CarSnapshot {
+        checkpoint: checkpoint.index,
+        x: checkpoint.x,
+        velocity: *velocity,
+        cargo,
+    });
+}
+
+/// The respawn action respawns at the last checkpoint, crashes can too depending on `CrashConfig`.
+#[allow(clippy::type_complexity, clippy::too_many_arguments)]
+pub fn respawn(
+    mut commands: Commands,
+    config: Res<CheckpointConfig>,
+    actions: Res<Actions>,
+    mut events: EventReader<RespawnEvent>,
+    last: Res<LastCheckpoint>,
+    gen: Res<ChunkGen>,
+    asset_server: Res<AssetServer>,
+    presets: Res<Presets>,
+    lists: Res<Assets<PresetList>>,
+    vehicles: Res<Vehicles>,
+    vehicle_lists: Res<Assets<VehicleList>>,
+    selected: Res<SelectedVehicle>,
+    car: Query<Entity, Or<(With<Chassis>, With<Wheel>, With<Cargo>)>>,
+) {
+    let manual = actions
+        .just_pressed(Action::Respawn)
+        .then_some(RespawnEvent { keep_cargo: true });
+    let Some(event) = events.iter().last().or(manual.as_ref()) else {
+        return;
+    };
+    let Some(vehicle) = vehicle_lists
+        .get(&vehicles.0)
+        .and_then(|l| l.get(&selected.0))
+    else {
+        return;
+    };
+    car.for_each(|e| commands.entity(e).despawn_recursive());
+
+    let snapshot = last.0.as_ref();
+    let (point, normal) = gen.probe(snapshot.map_or(0., |s| s.x));
+    let transform = Transform::from_translation((point + normal * config.drop_height).extend(0.))
+        .with_rotation(Quat::from_rotation_z((-normal.x).atan2(normal.y)));
+    let chassis = spawn_car(&mut commands, &asset_server, vehicle, transform);
+    let velocity = snapshot.map_or(Velocity::zero(), |s| s.velocity);
+    commands.entity(chassis).insert(velocity);
+
+    let (Some(snapshot), Some(list), true) = (snapshot, lists.get(&presets.0), event.keep_cargo)
+    else {
+        return;
+    };
+    // Cargo whose preset is gone takes everything nailed onto it along,
+    // parents come first in the snapshot so their fate is already known
+    let mut resolved = Vec::<Option<&Preset>>::with_capacity(snapshot.cargo.len());
+    snapshot.cargo.iter().for_each(|c| {
+        let preset = list.by_name(&c.name);
+        if preset.is_none() {
+            warn!("Unknown package preset {}", c.name);
+        }
+        let parent_kept = c.parent.is_none_or(|i| resolved[i].is_some());
+        resolved.push(preset.filter(|_| parent_kept));
+    });
+    // Everything is spawned first so cargo can be nailed onto cargo
+    let entities = resolved
+        .iter()
+        .zip(&snapshot.cargo)
+        .map(|(preset, c)| {
+            preset.map(|_| {
+                commands
+                    .spawn_bundle(TransformBundle::from(transform.mul_transform(c.transform)))
+                    .id()
+            })
+        })
+        .collect::<Vec<_>>();
+    snapshot
+        .cargo
+        .iter()
+        .zip(resolved.iter().zip(&entities))
+        .for_each(|(c, (preset, entity))| {
+            let (Some(preset), Some(entity)) = (preset, entity) else {
+                return;
+            };
+            let mut entity = commands.entity(*entity);
+            preset.apply(&mut entity, &asset_server);
+            if let Some(current) = c.durability {
+                entity.insert(Durability {
+                    current,
+                    ..preset.durability(&asset_server)
+                });
+            }
+            let parent = c.parent.and_then(|i| entities[i]).unwrap_or(chassis);
+            let cargo = Cargo {
+                name: c.name.clone(),
+                price: c.price,
+                powered: preset.powered,
+                flipped: c.flipped,
+            };
+            if let Err(e) = attach(&mut entity, parent, c.joint, c.mode, cargo) {
+                warn!("Can't nail {} back on: {e}", c.name);
+            }
+            entity.insert(velocity);
+        });
+}
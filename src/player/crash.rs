@@ -0,0 +1,149 @@
+use std::f32::consts::FRAC_PI_2;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    map::{chunk::ChunkGen, terrain::TerrainGenerator},
+    packages::{delivery::Money, presets::Cargo},
+    utils::{launch::launch_option, quat::rot_z},
+};
+
+use super::{
+    car::{Chassis, Wheel},
+    checkpoint::RespawnEvent,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrashOutcome {
+    EndRun,
+    /// Puts the car back on its wheels where it crashed, for a fee.
+    AutoRight {
+        penalty: u32,
+    },
+    /// Moves the car back to the last checkpoint, nailed cargo comes along or is lost.
+    Respawn {
+        keep_cargo: bool,
+    },
+}
+
+impl Default for CrashOutcome {
+    fn default() -> Self {
+        launch_option("crash")
+            .and_then(|value| Self::parse(&value))
+            .unwrap_or(Self::EndRun)
+    }
+}
+
+impl CrashOutcome {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "end" => Some(Self::EndRun),
+            "right" => Some(Self::AutoRight { penalty: 5 }),
+            "respawn" => Some(Self::Respawn { keep_cargo: true }),
+            "respawn-empty" => Some(Self::Respawn { keep_cargo: false }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CrashConfig {
+    /// Angle from upright past which the car is upside down.
+    pub flip_angle: f32,
+    /// Slower than this a car past its side and touching something rests on its roof.
+    pub rest_speed: f32,
+    /// Seconds the car may stay upside down or on its roof.
+    pub grace: f32,
+    pub outcome: CrashOutcome,
+    /// Height above the terrain a recovered car is dropped from.
+    pub drop_height: f32,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        Self {
+            flip_angle: 2.1,
+            rest_speed: 30.,
+            grace: 2.5,
+            outcome: CrashOutcome::default(),
+            drop_height: 100.,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct CrashEvent {
+    pub position: Vec2,
+    pub outcome: CrashOutcome,
+}
+
+pub fn detect_crash(
+    config: Res<CrashConfig>,
+    time: Res<Time>,
+    ctx: Res<RapierContext>,
+    chassis: Query<(Entity, &Transform, &Velocity), With<Chassis>>,
+    mut upside_down: Local<f32>,
+    mut events: EventWriter<CrashEvent>,
+) {
+    let Some((entity, transform, velocity)) = chassis.iter().next() else {
+        return;
+    };
+    let angle = rot_z(transform.rotation).abs();
+    let on_roof = angle > FRAC_PI_2
+        && velocity.linvel.length() < config.rest_speed
+        && ctx
+            .contacts_with(entity)
+            .any(|pair| pair.has_any_active_contacts());
+    if angle > config.flip_angle || on_roof {
+        *upside_down += time.delta_seconds();
+    } else {
+        *upside_down = 0.;
+    }
+    if *upside_down > config.grace {
+        *upside_down = 0.;
+        events.send(CrashEvent {
+            position: transform.translation.truncate(),
+            outcome: config.outcome,
+        });
+    }
+}
+
+/// Handles the outcomes that keep the run going.
+#[allow(clippy::type_complexity)]
+pub fn recover(
+    mut commands: Commands,
+    config: Res<CrashConfig>,
+    gen: Res<ChunkGen>,
+    mut money: ResMut<Money>,
+    mut events: EventReader<CrashEvent>,
+    mut respawns: EventWriter<RespawnEvent>,
+    mut parts: Query<
+        (Entity, &mut Transform, Option<&Chassis>),
+        Or<(With<Chassis>, With<Wheel>, With<Cargo>)>,
+    >,
+) {
+    events.iter().for_each(|event| match event.outcome {
+        CrashOutcome::EndRun => {}
+        CrashOutcome::AutoRight { penalty } => {
+            money.0 = money.0.saturating_sub(penalty);
+            money.save();
+            let Some(chassis) = parts
+                .iter()
+                .find_map(|(_, transform, chassis)| chassis.map(|_| *transform))
+            else {
+                return;
+            };
+            let x = event.position.x;
+            let target =
+                Transform::from_xyz(x, gen.height(x) + config.drop_height, chassis.translation.z);
+            // The whole car moves as one so the joints keep their layout
+            let delta = target.compute_matrix() * chassis.compute_matrix().inverse();
+            parts.for_each_mut(|(entity, mut transform, _)| {
+                *transform = Transform::from_matrix(delta * transform.compute_matrix());
+                commands.entity(entity).insert(Velocity::zero());
+            });
+        }
+        CrashOutcome::Respawn { keep_cargo } => respawns.send(RespawnEvent { keep_cargo }),
+    });
+}
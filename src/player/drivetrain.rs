@@ -0,0 +1,243 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::*;
+
+use crate::{
+    controls::{Action, Actions},
+    utils::launch::launch_option,
+};
+
+use super::car::{Chassis, Wheel};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriveLayout {
+    /// Whichever wheels the vehicle definition drives.
+    Vehicle,
+    Front,
+    Rear,
+    All,
+}
+
+impl DriveLayout {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "vehicle" => Some(Self::Vehicle),
+            "front" => Some(Self::Front),
+            "rear" => Some(Self::Rear),
+            "all" => Some(Self::All),
+            _ => None,
+        }
+    }
+
+    fn drives(&self, wheel: &Wheel) -> bool {
+        match self {
+            DriveLayout::Vehicle => wheel.driven,
+            DriveLayout::Front => wheel.front,
+            DriveLayout::Rear => !wheel.front,
+            DriveLayout::All => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Transmission {
+    /// Shifts automatically between the ratios.
+    Gears {
+        ratios: Vec<f32>,
+        shift_up_rpm: f32,
+        shift_down_rpm: f32,
+    },
+    /// Picks whatever ratio keeps the engine at `target_rpm`.
+    Cvt {
+        min_ratio: f32,
+        max_ratio: f32,
+        target_rpm: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct DrivetrainConfig {
+    /// Engine torque over rpm, linearly interpolated between the points.
+    pub torque_curve: Vec<Vec2>,
+    pub idle_rpm: f32,
+    /// The engine stops pulling past this.
+    pub max_rpm: f32,
+    pub transmission: Transmission,
+    pub final_drive: f32,
+    pub layout: DriveLayout,
+    /// Per wheel at a full brake.
+    pub brake_torque: f32,
+    /// Applied to the chassis only while no wheel touches anything.
+    pub air_torque: f32,
+    /// Wheels are neither driven nor allowed to spin faster than this, in radians per second.
+    pub max_wheel_speed: f32,
+}
+
+impl Default for DrivetrainConfig {
+    fn default() -> Self {
+        let transmission = match launch_option("gearbox").as_deref() {
+            Some("cvt") => Transmission::Cvt {
+                min_ratio: 0.8,
+                max_ratio: 3.2,
+                target_rpm: 4500.,
+            },
+            _ => Transmission::Gears {
+                ratios: vec![3.2, 2.1, 1.5, 1.1, 0.85],
+                shift_up_rpm: 6200.,
+                shift_down_rpm: 3000.,
+            },
+        };
+        Self {
+            torque_curve: vec![
+                Vec2::new(1000., 1.6),
+                Vec2::new(3000., 2.3),
+                Vec2::new(4500., 2.5),
+                Vec2::new(6500., 2.),
+                Vec2::new(7000., 1.5),
+            ],
+            idle_rpm: 1000.,
+            max_rpm: 7000.,
+            transmission,
+            final_drive: 4.,
+            layout: launch_option("drive")
+                .and_then(|value| DriveLayout::parse(&value))
+                .unwrap_or(DriveLayout::Vehicle),
+            brake_torque: 20.,
+            air_torque: 20.,
+            max_wheel_speed: 150.,
+        }
+    }
+}
+
+impl DrivetrainConfig {
+    pub fn torque(&self, rpm: f32) -> f32 {
+        let curve = &self.torque_curve;
+        let (Some(first), Some(last)) = (curve.first(), curve.last()) else {
+            return 0.;
+        };
+        if rpm <= first.x {
+            return first.y;
+        }
+        curve
+            .windows(2)
+            .find(|w| rpm <= w[1].x)
+            .map_or(last.y, |w| {
+                let t = (rpm - w[0].x) / (w[1].x - w[0].x);
+                w[0].y + (w[1].y - w[0].y) * t
+            })
+    }
+}
+
+/// What the drivetrain is doing right now.
+#[derive(Debug, Default)]
+pub struct Drivetrain {
+    pub gear: usize,
+    pub ratio: f32,
+    pub rpm: f32,
+    pub reversing: bool,
+}
+
+pub fn reset_drivetrain(mut drivetrain: ResMut<Drivetrain>) {
+    *drivetrain = Drivetrain::default();
+}
+
+fn to_rpm(angular_speed: f32) -> f32 {
+    angular_speed * 60. / (2. * PI)
+}
+
+#[allow(clippy::type_complexity)]
+pub fn drive(
+    config: Res<DrivetrainConfig>,
+    actions: Res<Actions>,
+    ctx: Res<RapierContext>,
+    mut drivetrain: ResMut<Drivetrain>,
+    mut wheels: Query<(Entity, &Wheel, &mut ExternalForce, &mut Velocity), Without<Chassis>>,
+    mut chassis: Query<&mut ExternalForce, With<Chassis>>,
+) {
+    let throttle = actions.value(Action::Accelerate);
+    let brake = actions.value(Action::Brake);
+
+    // Wheels spin clockwise going forward
+    let driven = wheels
+        .iter()
+        .filter(|(_, wheel, ..)| config.layout.drives(wheel))
+        .map(|(.., velocity)| -velocity.angvel)
+        .collect::<Vec<_>>();
+    let wheel_speed = driven.iter().sum::<f32>() / driven.len().max(1) as f32;
+
+    // Holding the brake at a standstill puts it in reverse
+    if brake > 0. && wheel_speed < 1. {
+        drivetrain.reversing = true;
+    } else if throttle > 0. || wheel_speed > 1. {
+        drivetrain.reversing = false;
+    }
+    let (pedal, brake, direction) = if drivetrain.reversing {
+        (brake, 0., -1.)
+    } else {
+        (throttle, brake, 1.)
+    };
+
+    let wheel_rpm = to_rpm(wheel_speed.abs());
+    drivetrain.ratio = match &config.transmission {
+        Transmission::Gears {
+            ratios,
+            shift_up_rpm,
+            shift_down_rpm,
+        } => {
+            let rpm = wheel_rpm * drivetrain.ratio * config.final_drive;
+            if drivetrain.reversing {
+                drivetrain.gear = 0;
+            } else if rpm > *shift_up_rpm && drivetrain.gear + 1 < ratios.len() {
+                drivetrain.gear += 1;
+            } else if rpm < *shift_down_rpm && drivetrain.gear > 0 {
+                drivetrain.gear -= 1;
+            }
+            ratios.get(drivetrain.gear).copied().unwrap_or(1.)
+        }
+        Transmission::Cvt {
+            min_ratio,
+            max_ratio,
+            target_rpm,
+        } => (target_rpm / (wheel_rpm * config.final_drive).max(1.)).clamp(*min_ratio, *max_ratio),
+    };
+    let total_ratio = drivetrain.ratio * config.final_drive;
+    drivetrain.rpm = (wheel_rpm * total_ratio).max(config.idle_rpm);
+
+    let engine_torque = if drivetrain.rpm < config.max_rpm {
+        config.torque(drivetrain.rpm) * pedal
+    } else {
+        0.
+    };
+    let wheel_torque = engine_torque * total_ratio / driven.len().max(1) as f32;
+
+    let mut grounded = false;
+    wheels.for_each_mut(|(entity, wheel, mut force, mut velocity)| {
+        grounded |= ctx
+            .contacts_with(entity)
+            .any(|pair| pair.has_any_active_contacts());
+        let spin = -velocity.angvel;
+        let drive = if config.layout.drives(wheel) && spin.abs() < config.max_wheel_speed {
+            wheel_torque * direction
+        } else {
+            0.
+        };
+        // Opposes the spin, scaled down near a standstill so it doesn't jitter
+        let braking = -spin.clamp(-1., 1.) * config.brake_torque * brake;
+        force.torque = -(drive + braking);
+        if spin.abs() > config.max_wheel_speed {
+            velocity.angvel = velocity
+                .angvel
+                .clamp(-config.max_wheel_speed, config.max_wheel_speed);
+        }
+    });
+
+    let tilt = actions.axis(Action::TiltBack, Action::TiltForward);
+    chassis.for_each_mut(|mut force| {
+        force.torque = if grounded {
+            0.
+        } else {
+            tilt * config.air_torque
+        };
+    });
+}
@@ -1,20 +1,64 @@
-use bevy::prelude::Plugin;
+use bevy::prelude::{AddAsset, ParallelSystemDescriptorCoercion, Plugin, SystemSet};
+
+use crate::run::GameState;
 
 use self::{
-    camera::{init_cam, follow_cam},
-    car::{movement, spawn_player_car},
+    camera::{follow_cam, init_cam, reset_cam},
+    car::spawn_player_car,
+    checkpoint::{
+        init, respawn, spawn_checkpoints, take_snapshot, CheckpointConfig, LastCheckpoint,
+        RespawnEvent,
+    },
+    crash::{detect_crash, recover, CrashConfig, CrashEvent},
+    drivetrain::{drive, reset_drivetrain, Drivetrain, DrivetrainConfig},
+    suspension::{apply_suspension, measure_suspension, suspension_panel, SuspensionPanel},
+    vehicles::{SelectedVehicle, VehicleList, VehicleLoader, Vehicles},
 };
 
 pub mod camera;
 pub mod car;
+pub mod checkpoint;
+pub mod crash;
+pub mod drivetrain;
+pub mod suspension;
+pub mod vehicles;
 
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_startup_system(init_cam)
-            .add_system(follow_cam)
-            .add_startup_system(spawn_player_car)
-            .add_system(movement);
+        app.add_asset::<VehicleList>()
+            .init_asset_loader::<VehicleLoader>()
+            .init_resource::<Vehicles>()
+            .init_resource::<SelectedVehicle>()
+            .init_resource::<CrashConfig>()
+            .init_resource::<DrivetrainConfig>()
+            .init_resource::<Drivetrain>()
+            .init_resource::<CheckpointConfig>()
+            .init_resource::<LastCheckpoint>()
+            .init_resource::<SuspensionPanel>()
+            .add_event::<CrashEvent>()
+            .add_event::<RespawnEvent>()
+            .add_startup_system(init_cam)
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(reset_cam)
+                    .with_system(spawn_player_car)
+                    .with_system(init)
+                    .with_system(reset_drivetrain),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(follow_cam)
+                    .with_system(drive)
+                    .with_system(apply_suspension)
+                    .with_system(measure_suspension)
+                    .with_system(suspension_panel)
+                    .with_system(detect_crash)
+                    .with_system(recover)
+                    .with_system(spawn_checkpoints)
+                    .with_system(take_snapshot)
+                    .with_system(respawn.after(recover)),
+            );
     }
 }
@@ -0,0 +1,156 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+use bevy_rapier2d::{
+    prelude::{GenericJoint, MultibodyJoint},
+    rapier::prelude::JointAxis,
+};
+
+use super::car::{Chassis, Wheel};
+
+/// Spring holding a wheel below its anchor on the chassis, edited live in the tuning panel.
+#[derive(Debug, Clone, Copy, PartialEq, Component)]
+pub struct Suspension {
+    pub stiffness: f32,
+    pub damping: f32,
+    /// How far below its anchor the spring pulls the wheel.
+    pub rest_length: f32,
+    /// How far below its anchor the wheel can hang at most.
+    pub travel: f32,
+}
+
+impl Suspension {
+    pub fn apply(&self, joint: &mut GenericJoint) {
+        joint.set_limits(JointAxis::X, [-self.travel, 0.]);
+        joint.set_motor_position(
+            JointAxis::X,
+            -self.rest_length.min(self.travel),
+            self.stiffness,
+            self.damping,
+        );
+    }
+}
+
+/// How far the wheel is pushed up, from 0 hanging at full travel to 1 against its anchor.
+#[derive(Debug, Default, Component)]
+pub struct Compression {
+    pub current: f32,
+    /// Highest since the car spawned or the panel reset it.
+    pub peak: f32,
+}
+
+pub fn apply_suspension(
+    mut wheels: Query<(&Suspension, &mut MultibodyJoint), Changed<Suspension>>,
+) {
+    wheels.for_each_mut(|(suspension, mut joint)| suspension.apply(&mut joint.data));
+}
+
+pub fn measure_suspension(
+    chassis: Query<&Transform, With<Chassis>>,
+    mut wheels: Query<(&Suspension, &MultibodyJoint, &Transform, &mut Compression), With<Wheel>>,
+) {
+    wheels.for_each_mut(|(suspension, joint, transform, mut compression)| {
+        let Ok(chassis) = chassis.get(joint.parent) else {
+            return;
+        };
+        let local = chassis
+            .compute_matrix()
+            .inverse()
+            .transform_point3(transform.translation);
+        let extension = joint.data.local_anchor1().y - local.y;
+        compression.current = (1. - extension / suspension.travel.max(1.)).clamp(0., 1.);
+        compression.peak = compression.peak.max(compression.current);
+    });
+}
+
+#[derive(Debug, Default)]
+pub struct SuspensionPanel {
+    pub open: bool,
+}
+
+/// `F2` toggles the tuning panel.
+pub fn suspension_panel(
+    mut egui_context: ResMut<EguiContext>,
+    keys: Res<Input<KeyCode>>,
+    mut panel: ResMut<SuspensionPanel>,
+    mut wheels: Query<(&Wheel, &MultibodyJoint, &mut Suspension, &mut Compression)>,
+) {
+    if keys.just_pressed(KeyCode::F2) {
+        panel.open = !panel.open;
+    }
+    if !panel.open {
+        return;
+    }
+    let mut wheels = wheels.iter_mut().collect::<Vec<_>>();
+    // Front to back
+    wheels.sort_by(|a, b| {
+        let x = |joint: &MultibodyJoint| joint.data.local_anchor1().x;
+        x(b.1).total_cmp(&x(a.1))
+    });
+
+    let mut open = true;
+    egui::Window::new("Suspension")
+        .open(&mut open)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            wheels
+                .iter_mut()
+                .enumerate()
+                .for_each(|(i, (wheel, _, suspension, compression))| {
+                    let mut edited = **suspension;
+                    ui.collapsing(
+                        format!(
+                            "Wheel {} ({}{})",
+                            i + 1,
+                            if wheel.front { "front" } else { "rear" },
+                            if wheel.driven { ", driven" } else { "" }
+                        ),
+                        |ui| {
+                            ui.add(
+                                egui::Slider::new(&mut edited.stiffness, 0.0..=2000.)
+                                    .text("Stiffness"),
+                            );
+                            ui.add(
+                                egui::Slider::new(&mut edited.damping, 0.0..=200.).text("Damping"),
+                            );
+                            ui.add(egui::Slider::new(&mut edited.travel, 1.0..=60.).text("Travel"));
+                            ui.add(
+                                egui::Slider::new(&mut edited.rest_length, 0.0..=edited.travel)
+                                    .text("Rest length"),
+                            );
+                        },
+                    );
+                    ui.add(
+                        egui::ProgressBar::new(compression.current)
+                            .text(format!("{:.0}%", compression.current * 100.)),
+                    );
+                    ui.label(format!("Peak {:.0}%", compression.peak * 100.));
+                    // Only written back on an edit so the joint isn't rebuilt every frame
+                    if edited != **suspension {
+                        **suspension = edited;
+                    }
+                });
+            ui.horizontal(|ui| {
+                if ui.button("Reset peaks").clicked() {
+                    wheels
+                        .iter_mut()
+                        .for_each(|(.., compression)| compression.peak = 0.);
+                }
+                // Tuned values only last for the run, this is how they get into the vehicle file
+                if ui.button("Log values").clicked() {
+                    wheels.iter().for_each(|(_, joint, suspension, _)| {
+                        info!(
+                            "anchor: {:?}, stiffness: {}, damping: {}, rest_length: {}, travel: {}",
+                            joint.data.local_anchor1(),
+                            suspension.stiffness,
+                            suspension.damping,
+                            suspension.rest_length,
+                            suspension.travel
+                        );
+                    });
+                }
+            });
+        });
+    if !open {
+        panel.open = false;
+    }
+}
@@ -0,0 +1,137 @@
+use bevy::{
+    asset::{AssetLoader, BoxedFuture, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use serde::Deserialize;
+
+use crate::utils::storage;
+
+use super::suspension::Suspension;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WheelDef {
+    /// Where the suspension is mounted on the chassis.
+    pub anchor: Vec2,
+    pub radius: f32,
+    pub mass: f32,
+    pub sprite: String,
+    /// Size the sprite is drawn at, a bit larger than the wheel for the tire.
+    pub size: f32,
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+    pub stiffness: f32,
+    pub damping: f32,
+    /// How far the wheel hangs below its anchor when fully extended.
+    pub travel: f32,
+    /// How far below its anchor the spring pulls the wheel, defaults to `travel`.
+    #[serde(default)]
+    pub rest_length: Option<f32>,
+    #[serde(default)]
+    pub drive: bool,
+}
+
+impl WheelDef {
+    pub fn suspension(&self) -> Suspension {
+        Suspension {
+            stiffness: self.stiffness,
+            damping: self.damping,
+            rest_length: self.rest_length.unwrap_or(self.travel),
+            travel: self.travel,
+        }
+    }
+}
+
+fn default_friction() -> f32 {
+    1.
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VehicleDef {
+    pub name: String,
+    pub sprite: String,
+    /// Size the sprite is drawn at.
+    pub size: Vec2,
+    /// Sprite anchor as a fraction of its size.
+    #[serde(default)]
+    pub anchor: Vec2,
+    /// Convex parts of the chassis collider.
+    pub hulls: Vec<Vec<Vec2>>,
+    pub mass: f32,
+    pub wheels: Vec<WheelDef>,
+}
+
+impl VehicleDef {
+    pub fn total_mass(&self) -> f32 {
+        self.mass + self.wheels.iter().map(|w| w.mass).sum::<f32>()
+    }
+}
+
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "0d8e3c51-7a24-4c1b-b6f2-93e5a1c47d08"]
+pub struct VehicleList {
+    pub vehicles: Vec<VehicleDef>,
+}
+
+impl VehicleList {
+    /// Falls back to the first vehicle when the name is unknown.
+    pub fn get(&self, name: &str) -> Option<&VehicleDef> {
+        self.vehicles
+            .iter()
+            .find(|v| v.name == name)
+            .or_else(|| self.vehicles.first())
+    }
+}
+
+#[derive(Default)]
+pub struct VehicleLoader;
+
+impl AssetLoader for VehicleLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let list: VehicleList = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(list));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["vehicles.ron"]
+    }
+}
+
+pub const VEHICLES_PATH: &str = "cars.vehicles.ron";
+
+#[derive(Debug)]
+pub struct Vehicles(pub Handle<VehicleList>);
+
+impl FromWorld for Vehicles {
+    fn from_world(world: &mut World) -> Self {
+        Self(world.resource::<AssetServer>().load(VEHICLES_PATH))
+    }
+}
+
+/// Name of the vehicle picked in the garage, kept between sessions.
+#[derive(Debug)]
+pub struct SelectedVehicle(pub String);
+
+const SELECTED_KEY: &str = "flippingout_vehicle";
+
+impl Default for SelectedVehicle {
+    fn default() -> Self {
+        Self(storage::load(SELECTED_KEY).unwrap_or_default())
+    }
+}
+
+impl SelectedVehicle {
+    pub fn select(&mut self, name: &str) {
+        self.0 = name.to_string();
+        if let Err(e) = storage::save(SELECTED_KEY, name) {
+            error!("Failed to save vehicle: {e}");
+        }
+    }
+}
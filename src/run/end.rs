@@ -0,0 +1,88 @@
+use bevy::prelude::*;
+
+use crate::{
+    map::{chunk::ChunkGen, terrain::TerrainGenerator},
+    player::{
+        car::Chassis,
+        crash::{CrashEvent, CrashOutcome},
+    },
+};
+
+use super::{GameState, RunStats};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEnd {
+    Flipped,
+    Fell,
+    /// Ended from the pause menu.
+    Retired,
+}
+
+impl RunEnd {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            RunEnd::Flipped => "Flipped over!",
+            RunEnd::Fell => "Fell off the world!",
+            RunEnd::Retired => "Retired",
+        }
+    }
+}
+
+/// Conditions ending a run, `None` disables a condition.
+#[derive(Debug)]
+pub struct RunEndConfig {
+    /// How far below the terrain the car may fall.
+    pub fall_depth: Option<f32>,
+    /// Seconds the wreck is shown before the results.
+    pub results_delay: f32,
+}
+
+impl Default for RunEndConfig {
+    fn default() -> Self {
+        Self {
+            fall_depth: Some(1000.),
+            results_delay: 2.,
+        }
+    }
+}
+
+/// Flipping over is decided by the crash outcome, see `CrashConfig`.
+pub fn check_run_end(
+    config: Res<RunEndConfig>,
+    gen: Res<ChunkGen>,
+    chassis: Query<&Transform, With<Chassis>>,
+    mut crashes: EventReader<CrashEvent>,
+    mut stats: ResMut<RunStats>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let Some(chassis) = chassis.iter().next() else {
+        return;
+    };
+    let position = chassis.translation;
+    let end = if crashes
+        .iter()
+        .any(|crash| crash.outcome == CrashOutcome::EndRun)
+    {
+        Some(RunEnd::Flipped)
+    } else if config
+        .fall_depth
+        .is_some_and(|d| position.y < gen.height(position.x) - d)
+    {
+        Some(RunEnd::Fell)
+    } else {
+        None
+    };
+    if let Some(end) = end {
+        stats.end = Some(end);
+        let _ = state.set(GameState::RunOver);
+    }
+}
+
+pub struct RunOverTimer(pub Timer);
+
+pub fn start_run_over(mut commands: Commands, config: Res<RunEndConfig>) {
+    commands.insert_resource(RunOverTimer(Timer::from_seconds(
+        config.results_delay,
+        false,
+    )));
+}
@@ -0,0 +1,110 @@
+use bevy::prelude::*;
+use bevy_rapier2d::prelude::RapierConfiguration;
+
+use crate::{
+    controls::{Action, Actions},
+    packages::delivery::DeliveryEvent,
+    player::{car::Chassis, crash::CrashEvent},
+};
+
+use self::{
+    end::{check_run_end, start_run_over, RunEnd, RunEndConfig},
+    screens::{garage, main_menu, pause_menu, results, run_over},
+};
+
+pub mod end;
+pub mod screens;
+
+/// Phases of the game, gameplay systems only run while `Playing`.
+/// `Paused` is pushed on top of `Playing` so the run survives it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GameState {
+    Menu,
+    /// Vehicle selection, reached from the menu.
+    Garage,
+    Playing,
+    Paused,
+    /// The run has ended but the wreck is still shown for a moment.
+    RunOver,
+    Results,
+}
+
+#[derive(Debug, Default)]
+pub struct RunStats {
+    /// Furthest distance reached in meters.
+    pub distance: f32,
+    pub time: f32,
+    pub deliveries: u32,
+    pub crashes: u32,
+    pub earned: u32,
+    pub end: Option<RunEnd>,
+}
+
+fn start_run(mut stats: ResMut<RunStats>) {
+    *stats = RunStats::default();
+}
+
+fn track_run(
+    time: Res<Time>,
+    mut stats: ResMut<RunStats>,
+    chassis: Query<&Transform, With<Chassis>>,
+    mut deliveries: EventReader<DeliveryEvent>,
+    mut crashes: EventReader<CrashEvent>,
+) {
+    stats.time += time.delta_seconds();
+    if let Some(chassis) = chassis.iter().next() {
+        // Physics runs at 100 pixels per meter
+        stats.distance = stats.distance.max(chassis.translation.x / 100.);
+    }
+    deliveries.iter().for_each(|event| {
+        stats.deliveries += 1;
+        stats.earned += event.payout;
+    });
+    stats.crashes += crashes.iter().count() as u32;
+}
+
+fn pause(mut actions: ResMut<Actions>, mut state: ResMut<State<GameState>>) {
+    // Consumed so the pause menu doesn't see the same press and resume right away
+    if actions.consume(Action::Pause) {
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+/// Despawns everything but the camera, each run starts from an empty world.
+fn teardown(mut commands: Commands, entities: Query<Entity, (Without<Parent>, Without<Camera>)>) {
+    entities.for_each(|e| commands.entity(e).despawn_recursive());
+}
+
+/// The world keeps moving while the wreck is shown, everywhere else it is frozen.
+fn freeze_physics(state: Res<State<GameState>>, mut config: ResMut<RapierConfiguration>) {
+    if state.is_changed() {
+        config.physics_pipeline_active =
+            matches!(state.current(), GameState::Playing | GameState::RunOver);
+    }
+}
+
+pub struct RunPlugin;
+
+impl Plugin for RunPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(GameState::Menu)
+            .init_resource::<RunStats>()
+            .init_resource::<RunEndConfig>()
+            .add_system(freeze_physics)
+            .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(teardown))
+            .add_system_set(SystemSet::on_update(GameState::Menu).with_system(main_menu))
+            .add_system_set(SystemSet::on_update(GameState::Garage).with_system(garage))
+            .add_system_set(SystemSet::on_enter(GameState::Playing).with_system(start_run))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(track_run)
+                    .with_system(check_run_end)
+                    .with_system(pause),
+            )
+            .add_system_set(SystemSet::on_update(GameState::Paused).with_system(pause_menu))
+            .add_system_set(SystemSet::on_enter(GameState::RunOver).with_system(start_run_over))
+            .add_system_set(SystemSet::on_update(GameState::RunOver).with_system(run_over))
+            .add_system_set(SystemSet::on_update(GameState::Results).with_system(results))
+            .add_system_set(SystemSet::on_exit(GameState::Results).with_system(teardown));
+    }
+}
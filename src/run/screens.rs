@@ -0,0 +1,186 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::bevy_egui::{egui, EguiContext};
+
+use crate::{
+    controls::{Action, Actions, ControlsWindow},
+    map::seed::WorldSeed,
+    packages::delivery::Money,
+    player::vehicles::{SelectedVehicle, VehicleList, Vehicles},
+};
+
+use super::{
+    end::{RunEnd, RunOverTimer},
+    GameState, RunStats,
+};
+
+fn window(title: &str) -> egui::Window<'_> {
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .collapsible(false)
+        .resizable(false)
+}
+
+/// An empty seed starts a random track.
+#[allow(clippy::too_many_arguments)]
+pub fn main_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<GameState>>,
+    mut seed: ResMut<WorldSeed>,
+    mut seed_text: Local<String>,
+    money: Res<Money>,
+    mut controls: ResMut<ControlsWindow>,
+    vehicles: Res<Vehicles>,
+    lists: Res<Assets<VehicleList>>,
+    selected: Res<SelectedVehicle>,
+) {
+    let vehicle = lists.get(&vehicles.0).and_then(|l| l.get(&selected.0));
+    window("Flipping Out").show(egui_context.ctx_mut(), |ui| {
+        ui.label(format!("Money: ${}", money.0));
+        ui.horizontal(|ui| {
+            ui.label("Seed");
+            ui.text_edit_singleline(&mut *seed_text);
+        });
+        ui.label(format!(
+            "Vehicle: {}",
+            vehicle.map_or("loading...", |v| v.name.as_str())
+        ));
+        // The car can't be spawned before the vehicles are loaded
+        if ui
+            .add_enabled(vehicle.is_some(), egui::Button::new("Start"))
+            .clicked()
+        {
+            *seed = match seed_text.trim() {
+                "" => WorldSeed::default(),
+                text => WorldSeed::parse(text),
+            };
+            let _ = state.set(GameState::Playing);
+        }
+        if ui.button("Garage").clicked() {
+            let _ = state.set(GameState::Garage);
+        }
+        if ui.button("Controls").clicked() {
+            controls.open = !controls.open;
+        }
+    });
+}
+
+pub fn garage(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<GameState>>,
+    vehicles: Res<Vehicles>,
+    lists: Res<Assets<VehicleList>>,
+    mut selected: ResMut<SelectedVehicle>,
+) {
+    let list = lists.get(&vehicles.0);
+    let current = list
+        .and_then(|l| l.get(&selected.0))
+        .map(|v| v.name.clone());
+    window("Garage").show(egui_context.ctx_mut(), |ui| {
+        if let Some(list) = list {
+            egui::Grid::new("vehicles").striped(true).show(ui, |ui| {
+                ui.strong("Vehicle");
+                ui.strong("Mass");
+                ui.strong("Wheels");
+                ui.strong("Driven");
+                ui.end_row();
+                list.vehicles.iter().for_each(|vehicle| {
+                    let is_current = current.as_deref() == Some(vehicle.name.as_str());
+                    if ui.selectable_label(is_current, &vehicle.name).clicked() {
+                        selected.select(&vehicle.name);
+                    }
+                    ui.label(format!("{:.0} kg", vehicle.total_mass()));
+                    ui.label(vehicle.wheels.len().to_string());
+                    ui.label(
+                        vehicle
+                            .wheels
+                            .iter()
+                            .filter(|w| w.drive)
+                            .count()
+                            .to_string(),
+                    );
+                    ui.end_row();
+                });
+            });
+        } else {
+            ui.label("Loading vehicles...");
+        }
+        if ui.button("Back").clicked() {
+            let _ = state.set(GameState::Menu);
+        }
+    });
+}
+
+pub fn pause_menu(
+    mut egui_context: ResMut<EguiContext>,
+    mut actions: ResMut<Actions>,
+    mut state: ResMut<State<GameState>>,
+    mut stats: ResMut<RunStats>,
+    mut controls: ResMut<ControlsWindow>,
+) {
+    let mut resume = actions.consume(Action::Pause);
+    window("Paused").show(egui_context.ctx_mut(), |ui| {
+        resume |= ui.button("Resume").clicked();
+        if ui.button("End run").clicked() {
+            stats.end = Some(RunEnd::Retired);
+            let _ = state.replace(GameState::Results);
+        }
+        if ui.button("Controls").clicked() {
+            controls.open = !controls.open;
+        }
+        if ui.button("Main menu").clicked() {
+            let _ = state.replace(GameState::Menu);
+        }
+    });
+    if resume {
+        let _ = state.pop();
+    }
+}
+
+pub fn run_over(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    timer: Option<ResMut<RunOverTimer>>,
+    mut state: ResMut<State<GameState>>,
+    stats: Res<RunStats>,
+) {
+    egui::Area::new("run_over")
+        .anchor(egui::Align2::CENTER_CENTER, [0., 0.])
+        .show(egui_context.ctx_mut(), |ui| {
+            if let Some(end) = stats.end {
+                ui.heading(end.describe());
+            }
+        });
+    if let Some(mut timer) = timer {
+        if timer.0.tick(time.delta()).finished() {
+            let _ = state.set(GameState::Results);
+        }
+    }
+}
+
+pub fn results(
+    mut egui_context: ResMut<EguiContext>,
+    mut state: ResMut<State<GameState>>,
+    stats: Res<RunStats>,
+    seed: Res<WorldSeed>,
+) {
+    window("Results").show(egui_context.ctx_mut(), |ui| {
+        if let Some(end) = stats.end {
+            ui.heading(end.describe());
+        }
+        ui.label(format!("Distance: {:.0} m", stats.distance));
+        ui.label(format!("Time: {:.1} s", stats.time));
+        ui.label(format!("Deliveries: {}", stats.deliveries));
+        ui.label(format!("Crashes: {}", stats.crashes));
+        ui.label(format!("Earned: ${}", stats.earned));
+        ui.label(format!("Seed: {}", seed.0));
+        ui.horizontal(|ui| {
+            // Same seed, same track
+            if ui.button("Retry").clicked() {
+                let _ = state.set(GameState::Playing);
+            }
+            if ui.button("Main menu").clicked() {
+                let _ = state.set(GameState::Menu);
+            }
+        });
+    });
+}
@@ -25,7 +25,7 @@ where
 type TryIntoResult<F, T> = Result<T, <T as TryFrom<F>>::Error>;
 
 pub trait IteratorExt: Iterator {
-    fn take_some(&mut self, n: usize) -> TakeSome<Self>
+    fn take_some(&mut self, n: usize) -> TakeSome<'_, Self>
     where
         Self: Sized,
     {
@@ -1,3 +1,5 @@
 pub mod iter;
+pub mod launch;
 pub mod secondary_handle;
+pub mod storage;
 pub mod quat;